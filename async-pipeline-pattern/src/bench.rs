@@ -1,12 +1,12 @@
 use std::time::{Duration, Instant};
 
+use alternative_async_pipeline::{Concurrency, Pipeline};
 use futures::{stream, StreamExt};
 use plotly::{
     layout::{Axis, AxisType, BarMode},
     Bar, ImageFormat, Layout, Plot,
 };
 
-use pumps::Concurrency;
 use rand::{rngs::StdRng, Rng, SeedableRng};
 
 async fn work(i: usize, duration: Duration) -> usize {
@@ -42,7 +42,7 @@ async fn run_with_stream(
     assert_eq!(sum, (n - 1) * n / 2);
 }
 
-async fn run_with_pumps(
+async fn run_with_pipeline(
     n: usize,
     timings1: Vec<Duration>,
     timings2: Vec<Duration>,
@@ -51,7 +51,7 @@ async fn run_with_pumps(
 ) {
     let input = 0..n;
 
-    let (mut reciver, handler) = pumps::Pipeline::from_iter(input)
+    let (mut receiver, handler) = Pipeline::from_iter(input)
         .map(
             move |i| work(i, timings1[i]),
             Concurrency::concurrent_unordered(concurrency),
@@ -67,7 +67,7 @@ async fn run_with_pumps(
         .build();
 
     let mut sum = 0usize;
-    while let Some(i) = reciver.recv().await {
+    while let Some(i) = receiver.recv().await {
         sum += i;
     }
 
@@ -77,7 +77,7 @@ async fn run_with_pumps(
     handler.await.unwrap();
 }
 
-async fn run_with_pumps_with_backpressure(
+async fn run_with_pipeline_with_backpressure(
     n: usize,
     timings1: Vec<Duration>,
     timings2: Vec<Duration>,
@@ -86,7 +86,7 @@ async fn run_with_pumps_with_backpressure(
 ) {
     let input = 0..n;
 
-    let (mut reciver, handler) = pumps::Pipeline::from_iter(input)
+    let (mut receiver, handler) = Pipeline::from_iter(input)
         .backpressure(128)
         .map(
             move |i| work(i, timings1[i]),
@@ -106,7 +106,7 @@ async fn run_with_pumps_with_backpressure(
         .build();
 
     let mut sum = 0usize;
-    while let Some(i) = reciver.recv().await {
+    while let Some(i) = receiver.recv().await {
         sum += i;
     }
 
@@ -152,7 +152,7 @@ async fn bench_by_concurrency(
 
     let mut x_labels = vec![];
     let mut stream_y_labels = vec![];
-    let mut pumps_y_labels = vec![];
+    let mut pipeline_y_labels = vec![];
 
     let mut rng: StdRng = SeedableRng::from_seed([100; 32]);
 
@@ -178,7 +178,7 @@ async fn bench_by_concurrency(
         stream_y_labels.push(start.elapsed().as_millis());
 
         let start = Instant::now();
-        run_with_pumps(
+        run_with_pipeline(
             n,
             timings.clone(),
             timings2.clone(),
@@ -186,8 +186,8 @@ async fn bench_by_concurrency(
             *concurrency,
         )
         .await;
-        println!("\t\tpumps runtime: {:?}", start.elapsed());
-        pumps_y_labels.push(start.elapsed().as_millis());
+        println!("\t\tpipeline runtime: {:?}", start.elapsed());
+        pipeline_y_labels.push(start.elapsed().as_millis());
     }
 
     let layout = Layout::new()
@@ -200,10 +200,10 @@ async fn bench_by_concurrency(
     plot.set_layout(layout);
 
     let stream_trace = Bar::new(x_labels.clone(), stream_y_labels).name("stream");
-    let pumps_trace = Bar::new(x_labels.clone(), pumps_y_labels).name("pumps");
+    let pipeline_trace = Bar::new(x_labels.clone(), pipeline_y_labels).name("pipeline");
 
     plot.add_trace(stream_trace.clone());
-    plot.add_trace(pumps_trace);
+    plot.add_trace(pipeline_trace);
 
     let filename = format!("concurrency_{:?}_{:?}", durations, concurrencies);
     plot.write_image(filename, ImageFormat::PNG, 600, 400, 1.0);
@@ -220,8 +220,8 @@ async fn bench_by_concurrency_with_backpressure(
     );
 
     let mut x_labels = vec![];
-    let mut pumps_bp_y_labels = vec![];
-    let mut pumps_y_labels = vec![];
+    let mut pipeline_bp_y_labels = vec![];
+    let mut pipeline_y_labels = vec![];
 
     let mut rng: StdRng = SeedableRng::from_seed([100; 32]);
 
@@ -235,7 +235,7 @@ async fn bench_by_concurrency_with_backpressure(
         println!("\tRunning with concurrency = {concurrency}");
 
         let start = Instant::now();
-        run_with_pumps_with_backpressure(
+        run_with_pipeline_with_backpressure(
             n,
             timings.clone(),
             timings2.clone(),
@@ -243,11 +243,14 @@ async fn bench_by_concurrency_with_backpressure(
             *concurrency,
         )
         .await;
-        println!("\t\tpumps with backpressure runtime: {:?}", start.elapsed());
-        pumps_bp_y_labels.push(start.elapsed().as_millis());
+        println!(
+            "\t\tpipeline with backpressure runtime: {:?}",
+            start.elapsed()
+        );
+        pipeline_bp_y_labels.push(start.elapsed().as_millis());
 
         let start = Instant::now();
-        run_with_pumps(
+        run_with_pipeline(
             n,
             timings.clone(),
             timings2.clone(),
@@ -255,8 +258,8 @@ async fn bench_by_concurrency_with_backpressure(
             *concurrency,
         )
         .await;
-        println!("\t\tpumps runtime: {:?}", start.elapsed());
-        pumps_y_labels.push(start.elapsed().as_millis());
+        println!("\t\tpipeline runtime: {:?}", start.elapsed());
+        pipeline_y_labels.push(start.elapsed().as_millis());
     }
 
     let layout = Layout::new()
@@ -268,12 +271,12 @@ async fn bench_by_concurrency_with_backpressure(
     let mut plot = Plot::new();
     plot.set_layout(layout);
 
-    let pumps_bp_trace =
-        Bar::new(x_labels.clone(), pumps_bp_y_labels).name("pumps /w backpressure");
-    let pumps_trace = Bar::new(x_labels.clone(), pumps_y_labels).name("pumps");
+    let pipeline_bp_trace =
+        Bar::new(x_labels.clone(), pipeline_bp_y_labels).name("pipeline /w backpressure");
+    let pipeline_trace = Bar::new(x_labels.clone(), pipeline_y_labels).name("pipeline");
 
-    plot.add_trace(pumps_trace);
-    plot.add_trace(pumps_bp_trace.clone());
+    plot.add_trace(pipeline_trace);
+    plot.add_trace(pipeline_bp_trace.clone());
 
     let filename = format!("backpressure_{:?}_{:?}.png", durations, concurrencies);
     plot.write_image(filename, ImageFormat::PNG, 600, 400, 1.0);
@@ -0,0 +1,53 @@
+/// How many items a stage works on at once, and whether its outputs keep the input order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Concurrency {
+    pub(crate) limit: usize,
+    pub(crate) ordered: bool,
+    /// Ordered stages only - how many items can be taken but not sent yet
+    pub(crate) reorder_window: usize,
+}
+
+impl Concurrency {
+    /// One item at a time, outputs keep the input order
+    pub fn serial() -> Self {
+        Self::concurrent_ordered(1)
+    }
+
+    /// Up to `limit` items at once, outputs keep the input order.
+    /// A slow item holds back the outputs of the items after it, and once `limit` items wait
+    /// behind it the stage stops taking new ones - see `reorder_window` to let it run further ahead
+    pub fn concurrent_ordered(limit: usize) -> Self {
+        assert!(limit > 0, "concurrency limit must be at least 1");
+        Concurrency {
+            limit,
+            ordered: true,
+            reorder_window: limit,
+        }
+    }
+
+    /// Up to `limit` items at once, outputs are sent as soon as they are ready
+    pub fn concurrent_unordered(limit: usize) -> Self {
+        assert!(limit > 0, "concurrency limit must be at least 1");
+        Concurrency {
+            limit,
+            ordered: false,
+            reorder_window: limit,
+        }
+    }
+
+    /// Lets an ordered stage take up to `window` items past the oldest one it hasn't sent yet,
+    /// keeping `limit` items running while a slow one holds back the output.
+    /// The results waiting for the slow item are buffered, so `window` bounds the memory it takes.
+    /// Only ordered stages have a reorder buffer, unordered ones send every result right away
+    pub fn reorder_window(self, window: usize) -> Self {
+        assert!(self.ordered, "only ordered stages have a reorder window");
+        assert!(
+            window >= self.limit,
+            "reorder window must be at least the concurrency limit"
+        );
+        Concurrency {
+            reorder_window: window,
+            ..self
+        }
+    }
+}
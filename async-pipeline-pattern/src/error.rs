@@ -0,0 +1,42 @@
+use std::fmt;
+
+use tokio::task::JoinError;
+
+/// Why a pipeline stopped before its input ran out
+#[derive(Debug)]
+pub enum PipelineError {
+    /// A stage with the `ErrorPolicy::abort` policy failed an item
+    Aborted {
+        stage: String,
+        error: String,
+    },
+    Panicked {
+        stage: String,
+        error: JoinError,
+    },
+    /// `PipelineHandle::shutdown` aborted these stages, they were still running after its timeout
+    ShutdownTimedOut {
+        stages: Vec<String>,
+    },
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::Aborted { stage, error } => {
+                write!(f, "stage `{stage}` aborted the pipeline: {error}")
+            }
+            PipelineError::Panicked { stage, error } => write!(f, "stage `{stage}` {error}"),
+            PipelineError::ShutdownTimedOut { stages } => {
+                write!(f, "shutdown timed out, aborted {}", stages.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+/// Returned by a stage task that aborted the pipeline, with the error message of the failed item.
+/// The stage doesn't know its name, `PipelineHandle` turns this into a `PipelineError`
+#[derive(Debug)]
+pub(crate) struct Aborted(pub(crate) String);
@@ -0,0 +1,13 @@
+mod concurrency;
+mod error;
+mod metrics;
+mod pipeline;
+mod policy;
+mod reorder;
+mod stage;
+
+pub use concurrency::Concurrency;
+pub use error::PipelineError;
+pub use metrics::{PipelineMetrics, Sample, StageSnapshot, TimeSeries};
+pub use pipeline::{Pipeline, PipelineHandle, PipelineReceiver};
+pub use policy::{Backoff, DeadLetter, ErrorPolicy};
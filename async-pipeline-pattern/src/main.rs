@@ -1,6 +1,10 @@
-use std::time::Duration;
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use futures::{stream::FuturesUnordered, StreamExt};
+use alternative_async_pipeline::{Backoff, Concurrency, ErrorPolicy, Pipeline};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use tokio::sync::mpsc;
 
 struct Image {
@@ -8,11 +12,19 @@ struct Image {
     data: Vec<u8>,
 }
 
-async fn download_image(url: String) -> Image {
+/// The seed used without `--seed`, so every run fails the same downloads
+const SEED: u64 = 42;
+
+/// Fails one download in ten, like a flaky server would
+async fn download_image(url: String, rng: Arc<Mutex<StdRng>>) -> Result<Image, String> {
     println!("downloading {url}");
     tokio::time::sleep(Duration::from_millis(5)).await;
 
-    Image { url, data: vec![0] }
+    let fails = rng.lock().unwrap().gen_bool(0.1);
+    if fails {
+        return Err(format!("server error while downloading {url}"));
+    }
+    Ok(Image { url, data: vec![0] })
 }
 
 async fn process_image(image: Image) -> Image {
@@ -26,75 +38,99 @@ async fn process_image(image: Image) -> Image {
 }
 
 async fn save_image(image: Image) {
-    println!("saving image {}", image.url);
+    println!("saving image {} ({} bytes)", image.url, image.data.len());
     tokio::time::sleep(Duration::from_millis(5)).await;
 }
 
-async fn async_pipeline_example() {
-    let urls = (0..32).map(|i| format!("https://example.com/image/{}", i));
-
-    let (url_sender, mut url_receiver) = mpsc::channel(100);
-    let (image_sender, mut image_receiver) = mpsc::channel(100);
-    let (processed_sender, mut processed_receiver) = mpsc::channel(100);
-    let (output_sender, mut output_receiver) = mpsc::channel(100);
-
-    let h1 = tokio::spawn(async move {
-        while let Some(url) = url_receiver.recv().await {
-            let image = download_image(url).await;
-            if let Err(err) = image_sender.send(image).await {
-                println!("failed to send output: {}", err);
-                break;
-            }
+/// The value following `flag` in the command line arguments
+fn flag_value(flag: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
         }
-    });
+    }
+    None
+}
+
+/// The file given with `--metrics <file>`, the example records its metrics there when set
+fn metrics_file() -> Option<String> {
+    flag_value("--metrics")
+}
+
+/// The seed given with `--seed <n>` for the failed downloads and the retry jitter, `SEED` without it
+fn seed() -> u64 {
+    flag_value("--seed").map_or(SEED, |seed| seed.parse().expect("--seed takes a number"))
+}
 
-    let h2 = tokio::spawn(async move {
+async fn async_pipeline_example() {
+    let urls = (0..32).map(|i| format!("https://example.com/image/{}", i));
+    // downloads that still fail after the retries end up here
+    let (failed_sender, mut failed_receiver) = mpsc::channel(32);
+    let seed = seed();
+    let rng = Arc::new(Mutex::new(StdRng::seed_from_u64(seed)));
+
+    let (mut output_receiver, join_handle) = Pipeline::from_iter(urls)
+        .try_map(
+            move |url| download_image(url, rng.clone()),
+            Concurrency::serial(),
+            ErrorPolicy::dead_letter(failed_sender)
+                .with_retry(Backoff::exponential(Duration::from_millis(10)).seed(seed)),
+        )
+        .name("download")
         // process concurrently up to 4 images
-        let mut futures = FuturesUnordered::new();
-
-        loop {
-            let in_progress_len = futures.len();
-
-            tokio::select! {
-                biased;
-
-                Some(image) = image_receiver.recv(), if in_progress_len < 4 => {
-                    futures.push(process_image(image));
-                },
-                Some(processed_image) = futures.next(), if in_progress_len > 0 => {
-                    if let Err(err) = processed_sender.send(processed_image).await {
-                        println!("failed to send output: {}", err);
-                        break;
-                    }
-                },
-                else => break
-            }
+        .map(process_image, Concurrency::concurrent_unordered(4))
+        .name("process")
+        .map(
+            |image: Image| async move {
+                let image_url = image.url.clone();
+                save_image(image).await;
+                image_url
+            },
+            Concurrency::serial(),
+        )
+        .name("save")
+        .build();
+
+    let metrics = join_handle.metrics();
+    let recording = metrics_file().map(|file| (file, metrics.record(Duration::from_millis(5))));
+
+    let consume = async {
+        while let Some(url) = output_receiver.recv().await {
+            println!("done with {url}");
         }
-    });
-
-    let h3 = tokio::spawn(async move {
-        while let Some(image) = processed_receiver.recv().await {
-            let image_url = image.url.clone();
-            save_image(image).await;
-            if let Err(err) = output_sender.send(image_url).await {
-                println!("failed to send output: {}", err);
-                break;
-            }
+    };
+    tokio::pin!(consume);
+
+    tokio::select! {
+        _ = &mut consume => join_handle.await.unwrap(),
+        _ = tokio::signal::ctrl_c() => {
+            println!("stopping, finishing the images already taken");
+            // keep reading the output while the stages drain
+            let shutdown = join_handle.shutdown(true, Duration::from_secs(1));
+            let ((), result) = tokio::join!(consume, shutdown);
+            result.unwrap();
         }
-    });
-
-    for url in urls {
-        url_sender.send(url).await.unwrap();
     }
 
-    // drop sender to make channel finite
-    drop(url_sender);
+    while let Ok(failed) = failed_receiver.try_recv() {
+        println!(
+            "gave up on {} after {} attempts: {}",
+            failed.item, failed.attempts, failed.error
+        );
+    }
 
-    while let Some(url) = output_receiver.recv().await {
-        println!("done with {url}");
+    for stage in metrics.snapshot() {
+        println!(
+            "{}: {} items, busy {:?}, idle {:?}",
+            stage.name, stage.items_out, stage.busy, stage.idle
+        );
     }
 
-    tokio::try_join!(h1, h2, h3).unwrap();
+    if let Some((file, recording)) = recording {
+        let series = recording.await.unwrap();
+        std::fs::write(file, series.to_json()).unwrap();
+    }
 }
 
 #[tokio::main()]
@@ -0,0 +1,423 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use plotly::{common::Mode, layout::Axis, Layout, Plot, Scatter};
+use tokio::{
+    sync::mpsc::Sender,
+    task::JoinHandle,
+    time::{self, Instant},
+};
+
+/// Counters a stage task updates as items go through it
+pub(crate) struct StageMetrics {
+    started: Instant,
+    capacity: usize,
+    state: Mutex<StageState>,
+}
+
+#[derive(Default)]
+struct StageState {
+    items_in: u64,
+    items_out: u64,
+    in_flight: usize,
+    retries: u64,
+    failures: u64,
+    busy: Duration,
+    busy_since: Option<Instant>,
+    finished: Option<Instant>,
+}
+
+impl StageMetrics {
+    pub(crate) fn new<T>(output: &Sender<T>) -> Self {
+        StageMetrics {
+            started: Instant::now(),
+            capacity: output.max_capacity(),
+            state: Mutex::default(),
+        }
+    }
+
+    /// An item was taken from the input, the stage starts working on it
+    pub(crate) fn received(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.items_in += 1;
+        state.in_flight += 1;
+        if state.in_flight == 1 {
+            state.busy_since = Some(Instant::now());
+        }
+    }
+
+    /// The stage finished working on an item, its result is about to be sent,
+    /// or waits in the reorder buffer of an ordered stage
+    pub(crate) fn completed(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight -= 1;
+        if state.in_flight == 0 {
+            if let Some(since) = state.busy_since.take() {
+                state.busy += since.elapsed();
+            }
+        }
+    }
+
+    pub(crate) fn sent(&self) {
+        self.state.lock().unwrap().items_out += 1;
+    }
+
+    pub(crate) fn retried(&self) {
+        self.state.lock().unwrap().retries += 1;
+    }
+
+    /// The error policy gave up on an item
+    pub(crate) fn failed(&self) {
+        self.state.lock().unwrap().failures += 1;
+    }
+
+    fn finish(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.finished.get_or_insert_with(Instant::now);
+    }
+
+    /// `queued` is filled in by `PipelineMetrics::snapshot`, which knows the next stage
+    fn snapshot(&self, name: &str) -> StageSnapshot {
+        let state = self.state.lock().unwrap();
+        let end = state.finished.unwrap_or_else(Instant::now);
+        let busy = state.busy + state.busy_since.map_or(Duration::ZERO, |since| end - since);
+        let elapsed = end - self.started;
+
+        StageSnapshot {
+            name: name.to_string(),
+            items_in: state.items_in,
+            items_out: state.items_out,
+            in_flight: state.in_flight,
+            retries: state.retries,
+            failures: state.failures,
+            queued: 0,
+            capacity: self.capacity,
+            busy,
+            idle: elapsed.saturating_sub(busy),
+            finished: state.finished.is_some(),
+        }
+    }
+}
+
+/// Marks a stage finished when its task ends, even by a panic
+pub(crate) struct FinishGuard(pub(crate) Arc<StageMetrics>);
+
+impl Drop for FinishGuard {
+    fn drop(&mut self) {
+        self.0.finish();
+    }
+}
+
+/// The state of a stage at one point in time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageSnapshot {
+    pub name: String,
+    /// Items taken from the stage's input
+    pub items_in: u64,
+    /// Results sent to the next stage
+    pub items_out: u64,
+    /// Items the stage is working on right now
+    pub in_flight: usize,
+    /// Failed attempts of `try_map` stages that were retried
+    pub retries: u64,
+    /// Items `try_map` stages gave up on
+    pub failures: u64,
+    /// Results waiting in the stage's output channel for the next stage, or the `PipelineReceiver`, to take them
+    pub queued: usize,
+    /// The capacity of the stage's output channel
+    pub capacity: usize,
+    /// Time spent with at least one item in flight
+    pub busy: Duration,
+    /// Time spent waiting for an input, or for room in the output channel
+    pub idle: Duration,
+    pub finished: bool,
+}
+
+/// The metrics of every stage of a pipeline, in pipeline order. Cheap to clone
+#[derive(Clone)]
+pub struct PipelineMetrics {
+    stages: Arc<[(String, Arc<StageMetrics>)]>,
+    /// Results the `PipelineReceiver` handed out
+    received: Arc<AtomicU64>,
+}
+
+impl PipelineMetrics {
+    pub(crate) fn new(stages: Vec<(String, Arc<StageMetrics>)>, received: Arc<AtomicU64>) -> Self {
+        PipelineMetrics {
+            stages: stages.into(),
+            received,
+        }
+    }
+
+    pub(crate) fn name(&self, stage: usize) -> &str {
+        &self.stages[stage].0
+    }
+
+    pub fn snapshot(&self) -> Vec<StageSnapshot> {
+        let mut snapshots: Vec<_> = self
+            .stages
+            .iter()
+            .map(|(name, stage)| stage.snapshot(name))
+            .collect();
+
+        // a channel keeps its items after the stage before it finished and dropped the sender,
+        // so the queue depth comes from what the stage sent and what the next one took out
+        let received = self.received.load(Ordering::Relaxed);
+        for i in 0..snapshots.len() {
+            let taken = snapshots.get(i + 1).map_or(received, |next| next.items_in);
+            snapshots[i].queued = snapshots[i].items_out.saturating_sub(taken) as usize;
+        }
+        snapshots
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.snapshot().iter().all(|stage| stage.finished)
+    }
+
+    /// Takes a snapshot every `interval` until every stage finished
+    pub fn record(&self, interval: Duration) -> JoinHandle<TimeSeries> {
+        let metrics = self.clone();
+
+        tokio::spawn(async move {
+            let start = Instant::now();
+            let mut interval = time::interval(interval);
+            let mut samples = Vec::new();
+
+            loop {
+                interval.tick().await;
+                let stages = metrics.snapshot();
+                let finished = stages.iter().all(|stage| stage.finished);
+                samples.push(Sample {
+                    at: start.elapsed(),
+                    stages,
+                });
+                if finished {
+                    break TimeSeries { samples };
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    /// Time since the recording started
+    pub at: Duration,
+    pub stages: Vec<StageSnapshot>,
+}
+
+/// Snapshots of a pipeline taken at a fixed interval, see `PipelineMetrics::record`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeSeries {
+    pub samples: Vec<Sample>,
+}
+
+impl TimeSeries {
+    /// A line per stage for its queue depth and one for its in flight items, over milliseconds
+    pub fn plot(&self) -> Plot {
+        let x: Vec<_> = self
+            .samples
+            .iter()
+            .map(|sample| sample.at.as_millis() as u64)
+            .collect();
+        let names = self
+            .samples
+            .first()
+            .map(|sample| sample.stages.iter().map(|stage| stage.name.clone()));
+
+        let mut plot = Plot::new();
+        for (i, name) in names.into_iter().flatten().enumerate() {
+            let series = |value: fn(&StageSnapshot) -> usize| -> Vec<usize> {
+                self.samples
+                    .iter()
+                    .map(|sample| value(&sample.stages[i]))
+                    .collect()
+            };
+
+            plot.add_trace(
+                Scatter::new(x.clone(), series(|stage| stage.queued))
+                    .mode(Mode::Lines)
+                    .name(format!("{name} queued")),
+            );
+            plot.add_trace(
+                Scatter::new(x.clone(), series(|stage| stage.in_flight))
+                    .mode(Mode::Lines)
+                    .name(format!("{name} in flight")),
+            );
+        }
+
+        plot.set_layout(
+            Layout::new()
+                .x_axis(Axis::new().title("milliseconds"))
+                .y_axis(Axis::new().title("items"))
+                .title("Pipeline stages over time"),
+        );
+        plot
+    }
+
+    /// The plot as plotly JSON, in the format of `backpressure.json`
+    pub fn to_json(&self) -> String {
+        self.plot().to_json()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::sleep;
+
+    use crate::{Concurrency, Pipeline};
+
+    use super::*;
+
+    async fn sleep_ms(ms: u64) {
+        sleep(Duration::from_millis(ms)).await;
+    }
+
+    #[tokio::test]
+    async fn stages_count_their_items() {
+        let (mut output, handle) = Pipeline::from_iter(0..20)
+            .map(
+                |i| async move { i * 2 },
+                Concurrency::concurrent_unordered(4),
+            )
+            .backpressure(8)
+            .map(|i| async move { i + 1 }, Concurrency::serial())
+            .name("add one")
+            .build();
+        let metrics = handle.metrics();
+
+        while output.recv().await.is_some() {}
+        handle.await.unwrap();
+
+        let snapshot = metrics.snapshot();
+        let names: Vec<_> = snapshot.iter().map(|stage| stage.name.as_str()).collect();
+        assert_eq!(names, ["source", "map 1", "backpressure 2", "add one"]);
+        for stage in &snapshot {
+            assert_eq!((stage.items_in, stage.items_out), (20, 20), "{stage:?}");
+            assert_eq!((stage.in_flight, stage.queued), (0, 0), "{stage:?}");
+            assert!(stage.finished, "{stage:?}");
+        }
+        assert_eq!(snapshot[1].capacity, 4);
+        assert_eq!(snapshot[2].capacity, 8);
+        assert!(metrics.is_finished());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn snapshots_show_a_running_pipeline() {
+        let (mut output, handle) = Pipeline::from_iter(0..100)
+            .map(|i| async move { i }, Concurrency::serial())
+            .backpressure(10)
+            .map(
+                |i| async move {
+                    sleep_ms(1000).await;
+                    i
+                },
+                Concurrency::concurrent_unordered(2),
+            )
+            .build();
+        let metrics = handle.metrics();
+
+        // the last stage is slow and the consumer doesn't read, so every channel fills up
+        sleep_ms(1).await;
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot[2].queued, 10, "{snapshot:?}");
+        assert_eq!(snapshot[3].in_flight, 2, "{snapshot:?}");
+        assert_eq!(snapshot[3].items_out, 0, "{snapshot:?}");
+
+        sleep_ms(2000).await;
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot[3].items_out, 2, "{snapshot:?}");
+        assert_eq!(snapshot[3].queued, 2, "{snapshot:?}");
+        assert!(!metrics.is_finished());
+
+        assert_eq!(output.recv().await, Some(0));
+    }
+
+    #[tokio::test]
+    async fn results_stay_queued_after_the_last_stage_finished() {
+        let (mut output, handle) = Pipeline::from_iter(0..5).backpressure(10).build();
+        let metrics = handle.metrics();
+
+        while !metrics.is_finished() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(metrics.snapshot()[1].queued, 5);
+
+        assert_eq!(output.recv().await, Some(0));
+        assert_eq!(metrics.snapshot()[1].queued, 4);
+
+        while output.recv().await.is_some() {}
+        assert_eq!(metrics.snapshot()[1].queued, 0);
+        handle.await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn busy_time_points_at_the_bottleneck() {
+        let (mut output, handle) = Pipeline::from_iter(0..10)
+            .map(
+                |i| async move {
+                    sleep_ms(1).await;
+                    i
+                },
+                Concurrency::serial(),
+            )
+            .name("fast")
+            .map(
+                |i| async move {
+                    sleep_ms(10).await;
+                    i
+                },
+                Concurrency::serial(),
+            )
+            .name("slow")
+            .build();
+        let metrics = handle.metrics();
+
+        while output.recv().await.is_some() {}
+        handle.await.unwrap();
+
+        let snapshot = metrics.snapshot();
+        let (fast, slow) = (&snapshot[1], &snapshot[2]);
+        assert_eq!(fast.busy, Duration::from_millis(10));
+        assert_eq!(slow.busy, Duration::from_millis(100));
+        assert!(fast.idle > fast.busy, "{fast:?}");
+        assert!(slow.idle <= Duration::from_millis(1), "{slow:?}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn record_samples_until_the_pipeline_finishes() {
+        let (mut output, handle) = Pipeline::from_iter(0..10)
+            .map(
+                |i| async move {
+                    sleep_ms(10).await;
+                    i
+                },
+                Concurrency::concurrent_unordered(2),
+            )
+            .name("download")
+            .build();
+        let recording = handle.metrics().record(Duration::from_millis(5));
+
+        while output.recv().await.is_some() {}
+        handle.await.unwrap();
+        let series = recording.await.unwrap();
+
+        // 5 rounds of 10ms, sampled every 5ms from 0
+        assert_eq!(series.samples.len(), 11);
+        assert_eq!(series.samples[1].at, Duration::from_millis(5));
+        assert_eq!(series.samples[1].stages[1].in_flight, 2);
+        let last = series.samples.last().unwrap();
+        assert!(last.stages.iter().all(|stage| stage.finished));
+
+        let json = series.to_json();
+        assert!(json.starts_with(r#"{"data":[{"type":"scatter","#), "{json}");
+        assert!(json.contains(r#""name":"download queued""#), "{json}");
+        assert!(json.contains(r#""name":"download in flight""#), "{json}");
+        assert!(json.ends_with(r#""config":{}}"#), "{json}");
+    }
+}
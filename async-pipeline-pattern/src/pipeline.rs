@@ -0,0 +1,619 @@
+use std::{
+    fmt::Display,
+    future::{Future, IntoFuture},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures::future::BoxFuture;
+use tokio::{
+    sync::mpsc::{self, Receiver, Sender},
+    task::JoinHandle,
+    time,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    error::Aborted,
+    metrics::{FinishGuard, StageMetrics},
+    stage, Concurrency, ErrorPolicy, PipelineError, PipelineMetrics,
+};
+
+/// Builds an async pipeline - every stage is a spawned task, connected to the next stage by a bounded channel.
+///
+/// ```no_run
+/// # use alternative_async_pipeline::{Concurrency, Pipeline};
+/// # async fn example() {
+/// let (mut output, handle) = Pipeline::from_iter(0..10)
+///     .map(|i| async move { i * 2 }, Concurrency::concurrent_unordered(4))
+///     .backpressure(64)
+///     .map(|i| async move { i + 1 }, Concurrency::serial())
+///     .build();
+///
+/// while let Some(i) = output.recv().await {
+///     println!("got {i}");
+/// }
+/// handle.await.unwrap();
+/// # }
+/// ```
+pub struct Pipeline<T> {
+    output: Receiver<T>,
+    handles: Vec<JoinHandle<Result<(), Aborted>>>,
+    stages: Vec<(String, Arc<StageMetrics>)>,
+    /// Stops every stage, dropping the items in flight
+    cancel: CancellationToken,
+    /// Stops the source only, the other stages finish once they drained their input
+    stop_input: CancellationToken,
+}
+
+impl<T> Pipeline<T>
+where
+    T: Send + 'static,
+{
+    /// A pipeline fed by a spawned task sending the items of `iter`, its stage is named "source"
+    #[allow(clippy::should_implement_trait)] // a `FromIterator` impl shouldn't spawn tasks
+    pub fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        I::IntoIter: Send + 'static,
+    {
+        let iter = iter.into_iter();
+        Self::from_source(|output, metrics| stage::source(iter, output, metrics))
+    }
+
+    /// A pipeline fed by `receiver`, it finishes once every sender is dropped.
+    /// Its "source" stage forwards the items, so `shutdown` can stop taking them
+    pub fn from_receiver(receiver: Receiver<T>) -> Self {
+        Self::from_source(|output, metrics| stage::forward(receiver, output, metrics))
+    }
+
+    fn from_source<Fut>(source: impl FnOnce(Sender<T>, Arc<StageMetrics>) -> Fut) -> Self
+    where
+        Fut: Future<Output = Result<(), Aborted>> + Send + 'static,
+    {
+        let cancel = CancellationToken::new();
+        let stop_input = cancel.child_token();
+
+        let (sender, output) = mpsc::channel(1);
+        let metrics = Arc::new(StageMetrics::new(&sender));
+        let handle = spawn_stage(
+            metrics.clone(),
+            stop_input.clone(),
+            source(sender, metrics.clone()),
+        );
+
+        Pipeline {
+            output,
+            handles: vec![handle],
+            stages: vec![("source".to_string(), metrics)],
+            cancel,
+            stop_input,
+        }
+    }
+
+    /// Adds a stage running `f` on every item. Up to `concurrency.limit` results wait in
+    /// the stage's output channel before the stage stops taking new items
+    pub fn map<U, F, Fut>(self, f: F, concurrency: Concurrency) -> Pipeline<U>
+    where
+        U: Send + 'static,
+        F: FnMut(T) -> Fut + Send + 'static,
+        Fut: Future<Output = U> + Send + 'static,
+    {
+        self.then("map", concurrency.limit, |input, output, metrics| {
+            stage::map(input, output, f, concurrency, metrics)
+        })
+    }
+
+    /// Like `map` for a fallible `f`, `policy` decides what happens to the items it fails on.
+    /// `f` is cloned for every item, so a failed item can be retried while the others go on
+    pub fn try_map<U, E, F, Fut>(
+        self,
+        f: F,
+        concurrency: Concurrency,
+        policy: ErrorPolicy<T, E>,
+    ) -> Pipeline<U>
+    where
+        T: Clone,
+        U: Send + 'static,
+        E: Display + Send + 'static,
+        F: FnMut(T) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = Result<U, E>> + Send + 'static,
+    {
+        self.then("try_map", concurrency.limit, |input, output, metrics| {
+            stage::try_map(input, output, f, concurrency, policy, metrics)
+        })
+    }
+
+    /// Adds a channel of `capacity` items, letting the previous stage run ahead of a slow next stage
+    pub fn backpressure(self, capacity: usize) -> Self {
+        self.then("backpressure", capacity, stage::forward)
+    }
+
+    /// Names the last stage in the metrics, instead of its kind and position like "map 2"
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        let (stage_name, _) = self
+            .stages
+            .last_mut()
+            .expect("the pipeline has no stage to name");
+        *stage_name = name.into();
+        self
+    }
+
+    /// The receiver of the last stage's outputs and a handle to the stage tasks.
+    /// Dropping the receiver stops the pipeline
+    pub fn build(self) -> (PipelineReceiver<T>, PipelineHandle) {
+        let received = Arc::new(AtomicU64::new(0));
+        (
+            PipelineReceiver {
+                receiver: self.output,
+                received: received.clone(),
+            },
+            PipelineHandle {
+                handles: self.handles,
+                metrics: PipelineMetrics::new(self.stages, received),
+                cancel: self.cancel,
+                stop_input: self.stop_input,
+            },
+        )
+    }
+
+    /// Spawns the stage created by `stage` from this pipeline's output and a new channel of `capacity`
+    fn then<U, Fut>(
+        self,
+        kind: &str,
+        capacity: usize,
+        stage: impl FnOnce(Receiver<T>, Sender<U>, Arc<StageMetrics>) -> Fut,
+    ) -> Pipeline<U>
+    where
+        U: Send + 'static,
+        Fut: Future<Output = Result<(), Aborted>> + Send + 'static,
+    {
+        let Pipeline {
+            output: input,
+            mut handles,
+            mut stages,
+            cancel,
+            stop_input,
+        } = self;
+
+        let (sender, output) = mpsc::channel(capacity);
+        let metrics = Arc::new(StageMetrics::new(&sender));
+        handles.push(spawn_stage(
+            metrics.clone(),
+            cancel.clone(),
+            stage(input, sender, metrics.clone()),
+        ));
+        stages.push((format!("{kind} {}", stages.len()), metrics));
+
+        Pipeline {
+            output,
+            handles,
+            stages,
+            cancel,
+            stop_input,
+        }
+    }
+}
+
+/// Receives the outputs of the last stage, counting them so the metrics can tell how many
+/// are still waiting in the channel, even after the last stage finished
+pub struct PipelineReceiver<T> {
+    receiver: Receiver<T>,
+    received: Arc<AtomicU64>,
+}
+
+impl<T> PipelineReceiver<T> {
+    /// The next output, `None` once the last stage finished and every output was received
+    pub async fn recv(&mut self) -> Option<T> {
+        let item = self.receiver.recv().await;
+        if item.is_some() {
+            self.received.fetch_add(1, Ordering::Relaxed);
+        }
+        item
+    }
+
+    /// The channel receiver, e.g. to feed another pipeline with `Pipeline::from_receiver`.
+    /// The metrics don't count the outputs received from it
+    pub fn into_inner(self) -> Receiver<T> {
+        self.receiver
+    }
+}
+
+/// Spawns `stage`, it stops once `cancel` is cancelled. Its channels and items in flight
+/// are dropped with it, so the stages around it stop too
+fn spawn_stage(
+    metrics: Arc<StageMetrics>,
+    cancel: CancellationToken,
+    stage: impl Future<Output = Result<(), Aborted>> + Send + 'static,
+) -> JoinHandle<Result<(), Aborted>> {
+    let finished = FinishGuard(metrics);
+    tokio::spawn(async move {
+        let _finished = finished;
+        tokio::select! {
+            biased;
+
+            _ = cancel.cancelled() => Ok(()),
+            result = stage => result,
+        }
+    })
+}
+
+/// The tasks of a built pipeline. Awaiting it waits for every stage to finish,
+/// and fails with the error of the first stage that aborted or panicked
+pub struct PipelineHandle {
+    handles: Vec<JoinHandle<Result<(), Aborted>>>,
+    metrics: PipelineMetrics,
+    cancel: CancellationToken,
+    stop_input: CancellationToken,
+}
+
+impl PipelineHandle {
+    /// The metrics of every stage, they can be read while the pipeline runs
+    pub fn metrics(&self) -> PipelineMetrics {
+        self.metrics.clone()
+    }
+
+    /// The token every stage watches, cancelling it stops them like `shutdown` without draining
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// Stops the pipeline and waits for its stages to finish.
+    ///
+    /// With `drain`, the source stops taking input and the other stages finish the items they
+    /// already have, which needs the output to be read until it closes. Without it, every stage
+    /// stops right away and the items in flight are dropped. Stages still running after `timeout`
+    /// are aborted, and `PipelineError::ShutdownTimedOut` lists them
+    pub async fn shutdown(self, drain: bool, timeout: Duration) -> Result<(), PipelineError> {
+        if drain {
+            self.stop_input.cancel();
+        } else {
+            self.cancel.cancel();
+        }
+
+        let cancel = self.cancel.clone();
+        let metrics = self.metrics.clone();
+        let abort_handles: Vec<_> = self.handles.iter().map(JoinHandle::abort_handle).collect();
+        let mut join = self.into_future();
+
+        if let Ok(result) = time::timeout(timeout, &mut join).await {
+            return result;
+        }
+
+        let stages = metrics
+            .snapshot()
+            .into_iter()
+            .filter(|stage| !stage.finished)
+            .map(|stage| stage.name)
+            .collect();
+        // a stage stuck in its own code doesn't see the token, aborting drops it at its next await
+        cancel.cancel();
+        for handle in abort_handles {
+            handle.abort();
+        }
+        join.await?;
+
+        Err(PipelineError::ShutdownTimedOut { stages })
+    }
+}
+
+impl IntoFuture for PipelineHandle {
+    type Output = Result<(), PipelineError>;
+    type IntoFuture = BoxFuture<'static, Result<(), PipelineError>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move {
+            // a failing stage drops its channels, so the other stages finish too
+            let mut result = Ok(());
+            for (i, handle) in self.handles.into_iter().enumerate() {
+                let stage = self.metrics.name(i).to_string();
+                let error = match handle.await {
+                    Ok(Ok(())) => continue,
+                    Ok(Err(Aborted(error))) => PipelineError::Aborted { stage, error },
+                    // only `shutdown` aborts stage tasks, and it reports that itself
+                    Err(error) if error.is_cancelled() => continue,
+                    Err(error) => PipelineError::Panicked { stage, error },
+                };
+                if result.is_ok() {
+                    result = Err(error);
+                }
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use tokio::time::{sleep, Instant};
+
+    use super::*;
+
+    async fn collect<T>(mut output: PipelineReceiver<T>, handle: PipelineHandle) -> Vec<T> {
+        let mut items = Vec::new();
+        while let Some(item) = output.recv().await {
+            items.push(item);
+        }
+        handle.await.unwrap();
+        items
+    }
+
+    /// Sleeps longer for lower numbers, so unordered stages reverse them
+    async fn reverse_delay(i: u64) -> u64 {
+        sleep(Duration::from_millis(100 - i * 10)).await;
+        i
+    }
+
+    #[tokio::test]
+    async fn map_runs_every_stage_on_every_item() {
+        let (output, handle) = Pipeline::from_iter(0..100)
+            .map(
+                |i| async move { i * 2 },
+                Concurrency::concurrent_unordered(4),
+            )
+            .map(|i| async move { i + 1 }, Concurrency::serial())
+            .build();
+
+        let items = collect(output, handle).await;
+
+        assert_eq!(items, (0..100).map(|i| i * 2 + 1).collect::<Vec<_>>());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ordered_stages_keep_the_input_order() {
+        let (output, handle) = Pipeline::from_iter(0..10)
+            .map(reverse_delay, Concurrency::concurrent_ordered(10))
+            .build();
+
+        assert_eq!(collect(output, handle).await, (0..10).collect::<Vec<_>>());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unordered_stages_send_outputs_when_ready() {
+        let (output, handle) = Pipeline::from_iter(0..10)
+            .map(reverse_delay, Concurrency::concurrent_unordered(10))
+            .build();
+
+        assert_eq!(
+            collect(output, handle).await,
+            (0..10).rev().collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stages_respect_the_concurrency_limit() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+
+        let (output, handle) = Pipeline::from_iter(0..50)
+            .map(
+                {
+                    let (running, max_running) = (running.clone(), max_running.clone());
+                    move |i: u64| {
+                        let (running, max_running) = (running.clone(), max_running.clone());
+                        async move {
+                            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                            max_running.fetch_max(now, Ordering::SeqCst);
+                            sleep(Duration::from_millis(1 + i % 7)).await;
+                            running.fetch_sub(1, Ordering::SeqCst);
+                        }
+                    }
+                },
+                Concurrency::concurrent_unordered(3),
+            )
+            .build();
+
+        assert_eq!(collect(output, handle).await.len(), 50);
+        assert_eq!(max_running.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stages_run_concurrently_with_each_other() {
+        let stage = |i: u32| async move {
+            sleep(Duration::from_millis(10)).await;
+            i
+        };
+        let start = Instant::now();
+
+        let (output, handle) = Pipeline::from_iter(0..10)
+            .map(stage, Concurrency::serial())
+            .map(stage, Concurrency::serial())
+            .map(stage, Concurrency::serial())
+            .build();
+        collect(output, handle).await;
+
+        // 10 items through 3 stages of 10ms, one after the other, would take 300ms
+        assert_eq!(start.elapsed(), Duration::from_millis(120));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backpressure_lets_a_stage_run_ahead() {
+        let produced = Arc::new(AtomicUsize::new(0));
+        let counter = produced.clone();
+
+        let (mut output, _handle) = Pipeline::from_iter(0..100)
+            .map(
+                move |i| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    async move { i }
+                },
+                Concurrency::serial(),
+            )
+            .backpressure(20)
+            .build();
+
+        // the consumer is stalled, only the channels keep the first stage going
+        sleep(Duration::from_secs(1)).await;
+        let ahead = produced.load(Ordering::SeqCst);
+        assert!((20..25).contains(&ahead), "{ahead} items produced");
+
+        assert_eq!(output.recv().await, Some(0));
+    }
+
+    #[tokio::test]
+    async fn dropping_the_output_stops_every_stage() {
+        let (mut output, handle) = Pipeline::from_iter(0..)
+            .map(
+                |i: u64| async move { i },
+                Concurrency::concurrent_unordered(4),
+            )
+            .backpressure(8)
+            .build();
+
+        assert!(output.recv().await.is_some());
+        drop(output);
+
+        handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn from_receiver_finishes_when_senders_are_dropped() {
+        let (sender, receiver) = mpsc::channel(4);
+        let (output, handle) = Pipeline::from_receiver(receiver)
+            .map(|s: &str| async move { s.len() }, Concurrency::serial())
+            .build();
+
+        sender.send("abc").await.unwrap();
+        sender.send("de").await.unwrap();
+        drop(sender);
+
+        assert_eq!(collect(output, handle).await, [3, 2]);
+    }
+
+    #[tokio::test]
+    async fn a_panicking_stage_fails_the_handle() {
+        let (mut output, handle) = Pipeline::from_iter(0..10)
+            .map(
+                |i: u32| async move {
+                    assert!(i < 5, "item {i} is too big");
+                    i
+                },
+                Concurrency::concurrent_ordered(2),
+            )
+            .build();
+
+        while output.recv().await.is_some() {}
+
+        let Err(PipelineError::Panicked { stage, error }) = handle.await else {
+            panic!("the pipeline should fail");
+        };
+        assert_eq!(stage, "map 1");
+        assert!(error.is_panic());
+    }
+
+    fn alive_tasks() -> usize {
+        tokio::runtime::Handle::current()
+            .metrics()
+            .num_alive_tasks()
+    }
+
+    /// Never finishes on its own, every stage is still running when the test shuts it down
+    fn endless_pipeline(delay: Duration) -> (PipelineReceiver<u64>, PipelineHandle) {
+        Pipeline::from_iter(0..)
+            .map(
+                move |i| async move {
+                    sleep(delay).await;
+                    i
+                },
+                Concurrency::concurrent_unordered(4),
+            )
+            .backpressure(8)
+            .map(|i| async move { i }, Concurrency::serial())
+            .build()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_without_drain_drops_the_items_in_flight() {
+        let tasks = alive_tasks();
+        let (mut output, handle) = endless_pipeline(Duration::from_secs(60));
+        let metrics = handle.metrics();
+        sleep(Duration::from_millis(10)).await;
+        assert_eq!(metrics.snapshot()[1].in_flight, 4);
+
+        let start = Instant::now();
+        handle
+            .shutdown(false, Duration::from_secs(1))
+            .await
+            .unwrap();
+
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert!(metrics.is_finished());
+        assert_eq!(alive_tasks(), tasks);
+        assert_eq!(output.recv().await, None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_with_drain_finishes_the_items_taken() {
+        let tasks = alive_tasks();
+        let (mut output, handle) = endless_pipeline(Duration::from_millis(10));
+        let metrics = handle.metrics();
+
+        let consumer = tokio::spawn(async move {
+            let mut items = Vec::new();
+            while let Some(item) = output.recv().await {
+                items.push(item);
+            }
+            items
+        });
+        sleep(Duration::from_millis(25)).await;
+        handle.shutdown(true, Duration::from_secs(1)).await.unwrap();
+        let items = consumer.await.unwrap();
+
+        // every item that left the source made it through
+        let source = &metrics.snapshot()[0];
+        assert!(items.len() > 4, "{items:?}");
+        assert_eq!(items.len() as u64, source.items_out);
+        assert!(metrics.is_finished());
+        assert_eq!(alive_tasks(), tasks);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shutdown_aborts_the_stages_still_draining_after_the_timeout() {
+        let tasks = alive_tasks();
+        let (output, handle) = Pipeline::from_iter(0..)
+            .map(|i: u64| async move { i }, Concurrency::serial())
+            .name("identity")
+            .backpressure(8)
+            .build();
+        let metrics = handle.metrics();
+        sleep(Duration::from_millis(10)).await;
+
+        // nobody reads the output, so draining can't finish
+        let start = Instant::now();
+        let result = handle.shutdown(true, Duration::from_millis(50)).await;
+
+        assert_eq!(start.elapsed(), Duration::from_millis(50));
+        let Err(PipelineError::ShutdownTimedOut { stages }) = result else {
+            panic!("shutdown should time out, got {result:?}");
+        };
+        assert_eq!(stages, ["identity", "backpressure 2"]);
+        assert!(metrics.is_finished());
+        assert_eq!(alive_tasks(), tasks);
+        drop(output);
+    }
+
+    #[tokio::test]
+    async fn cancelling_the_token_stops_every_stage() {
+        let tasks = alive_tasks();
+        let (mut output, handle) = endless_pipeline(Duration::from_millis(1));
+        let token = handle.cancellation_token();
+
+        assert!(output.recv().await.is_some());
+        token.cancel();
+        handle.await.unwrap();
+
+        while output.recv().await.is_some() {}
+        assert_eq!(alive_tasks(), tasks);
+    }
+}
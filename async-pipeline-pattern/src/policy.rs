@@ -0,0 +1,453 @@
+use std::{
+    fmt::Display,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::sync::mpsc::Sender;
+
+use crate::{metrics::StageMetrics, stage::Outcome};
+
+/// Waits `initial` before the first retry, doubling the wait up to `max_delay` for later ones.
+/// With jitter, each wait is picked at random from its upper half, so failed items don't retry in lockstep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backoff {
+    initial: Duration,
+    max_delay: Duration,
+    retries: u32,
+    jitter: bool,
+    seed: Option<u64>,
+}
+
+impl Backoff {
+    /// 3 retries with jitter, waiting at most 10 seconds
+    pub fn exponential(initial: Duration) -> Self {
+        Backoff {
+            initial,
+            max_delay: Duration::from_secs(10),
+            retries: 3,
+            jitter: true,
+            seed: None,
+        }
+    }
+
+    pub fn retries(self, retries: u32) -> Self {
+        Backoff { retries, ..self }
+    }
+
+    pub fn max_delay(self, max_delay: Duration) -> Self {
+        Backoff { max_delay, ..self }
+    }
+
+    pub fn without_jitter(self) -> Self {
+        Backoff {
+            jitter: false,
+            ..self
+        }
+    }
+
+    /// Picks the jitter with an rng seeded with `seed` instead of a random one, so a run can be repeated
+    pub fn seed(self, seed: u64) -> Self {
+        Backoff {
+            seed: Some(seed),
+            ..self
+        }
+    }
+
+    /// The rng the jitter is picked with
+    pub(crate) fn rng(&self) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        }
+    }
+
+    /// The wait before retry number `retry`, counting from 0
+    pub(crate) fn delay(&self, retry: u32, rng: &mut impl Rng) -> Duration {
+        let delay = self
+            .initial
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay);
+
+        if self.jitter {
+            rng.gen_range(delay / 2..=delay)
+        } else {
+            delay
+        }
+    }
+}
+
+/// An item a stage gave up on, sent to the dead letter channel of `ErrorPolicy::dead_letter`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadLetter<T, E> {
+    pub item: T,
+    /// The error of the last attempt
+    pub error: E,
+    pub attempts: u32,
+}
+
+/// What a `try_map` stage does with an item `f` failed on, optionally after retrying it
+pub struct ErrorPolicy<T, E> {
+    retry: Option<Retry>,
+    on_failure: OnFailure<T, E>,
+}
+
+/// The backoff with the rng its jitter is picked with, shared by the clones of the policy
+#[derive(Clone)]
+struct Retry {
+    backoff: Backoff,
+    rng: Arc<Mutex<StdRng>>,
+}
+
+enum OnFailure<T, E> {
+    Drop,
+    DeadLetter(Sender<DeadLetter<T, E>>),
+    Abort,
+}
+
+impl<T, E> Clone for ErrorPolicy<T, E> {
+    fn clone(&self) -> Self {
+        let on_failure = match &self.on_failure {
+            OnFailure::Drop => OnFailure::Drop,
+            OnFailure::DeadLetter(sender) => OnFailure::DeadLetter(sender.clone()),
+            OnFailure::Abort => OnFailure::Abort,
+        };
+
+        ErrorPolicy {
+            retry: self.retry.clone(),
+            on_failure,
+        }
+    }
+}
+
+impl<T, E> ErrorPolicy<T, E> {
+    /// Drops the failed item, the stage goes on with the next ones
+    pub fn drop() -> Self {
+        Self::on_failure(OnFailure::Drop)
+    }
+
+    /// Retries with `backoff`, then drops the item if it still fails
+    pub fn retry(backoff: Backoff) -> Self {
+        Self::drop().with_retry(backoff)
+    }
+
+    /// Sends the failed item to `sender`. The stage waits for room in the dead letter channel,
+    /// so read it while the pipeline runs, or give it enough capacity
+    pub fn dead_letter(sender: Sender<DeadLetter<T, E>>) -> Self {
+        Self::on_failure(OnFailure::DeadLetter(sender))
+    }
+
+    /// Stops the pipeline, awaiting the `PipelineHandle` returns `PipelineError::Aborted`
+    pub fn abort() -> Self {
+        Self::on_failure(OnFailure::Abort)
+    }
+
+    /// Retries failed items with `backoff` before applying the policy
+    pub fn with_retry(self, backoff: Backoff) -> Self {
+        let rng = Arc::new(Mutex::new(backoff.rng()));
+        ErrorPolicy {
+            retry: Some(Retry { backoff, rng }),
+            ..self
+        }
+    }
+
+    fn on_failure(on_failure: OnFailure<T, E>) -> Self {
+        ErrorPolicy {
+            retry: None,
+            on_failure,
+        }
+    }
+
+    /// Calls `f` on `item` until it succeeds or the retries run out, then applies the policy
+    pub(crate) async fn call<U, F, Fut>(
+        &self,
+        item: T,
+        mut f: F,
+        metrics: &StageMetrics,
+    ) -> Outcome<U>
+    where
+        T: Clone,
+        E: Display,
+        F: FnMut(T) -> Fut,
+        Fut: Future<Output = Result<U, E>>,
+    {
+        let mut retry = 0;
+        let error = loop {
+            match (f(item.clone()).await, &self.retry) {
+                (Ok(output), _) => return Outcome::Send(output),
+                (Err(_), Some(Retry { backoff, rng })) if retry < backoff.retries => {
+                    metrics.retried();
+                    let delay = backoff.delay(retry, &mut *rng.lock().unwrap());
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                }
+                (Err(error), _) => break error,
+            }
+        };
+
+        metrics.failed();
+        match &self.on_failure {
+            OnFailure::Drop => Outcome::Skip,
+            OnFailure::DeadLetter(sender) => {
+                let dead_letter = DeadLetter {
+                    item,
+                    error,
+                    attempts: retry + 1,
+                };
+                // without a dead letter receiver the item is dropped
+                let _ = sender.send(dead_letter).await;
+                Outcome::Skip
+            }
+            OnFailure::Abort => Outcome::Abort(error.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fmt,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use futures::future::BoxFuture;
+    use rand::{rngs::StdRng, SeedableRng};
+    use tokio::{sync::mpsc, time::sleep};
+
+    use crate::{Concurrency, Pipeline, PipelineError};
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Image {
+        url: String,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct DownloadError(String);
+
+    impl fmt::Display for DownloadError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "failed to download {}", self.0)
+        }
+    }
+
+    /// A `download_image` failing at random with a seeded rng, so every run fails the same attempts
+    #[derive(Clone)]
+    struct FlakyDownloader {
+        rng: Arc<Mutex<StdRng>>,
+        failure_rate: f64,
+        failures: Arc<AtomicU64>,
+    }
+
+    impl FlakyDownloader {
+        fn new(seed: u64, failure_rate: f64) -> Self {
+            FlakyDownloader {
+                rng: Arc::new(Mutex::new(StdRng::seed_from_u64(seed))),
+                failure_rate,
+                failures: Arc::default(),
+            }
+        }
+
+        async fn download_image(&self, url: String) -> Result<Image, DownloadError> {
+            sleep(Duration::from_millis(5)).await;
+
+            if self.rng.lock().unwrap().gen_bool(self.failure_rate) {
+                self.failures.fetch_add(1, Ordering::SeqCst);
+                return Err(DownloadError(url));
+            }
+            Ok(Image { url })
+        }
+
+        fn stage(
+            &self,
+        ) -> impl FnMut(String) -> BoxFuture<'static, Result<Image, DownloadError>> + Clone
+        {
+            let downloader = self.clone();
+            move |url| {
+                let downloader = downloader.clone();
+                Box::pin(async move { downloader.download_image(url).await })
+            }
+        }
+
+        fn failures(&self) -> u64 {
+            self.failures.load(Ordering::SeqCst)
+        }
+    }
+
+    fn urls(n: usize) -> Vec<String> {
+        (0..n)
+            .map(|i| format!("https://example.com/image/{i}"))
+            .collect()
+    }
+
+    async fn download_all(
+        downloader: &FlakyDownloader,
+        concurrency: Concurrency,
+        policy: ErrorPolicy<String, DownloadError>,
+    ) -> (Vec<String>, Result<(), PipelineError>, crate::StageSnapshot) {
+        let (mut output, handle) = Pipeline::from_iter(urls(50))
+            .try_map(downloader.stage(), concurrency, policy)
+            .name("download")
+            .build();
+        let metrics = handle.metrics();
+
+        let mut downloaded = Vec::new();
+        while let Some(image) = output.recv().await {
+            downloaded.push(image.url);
+        }
+        let result = handle.await;
+        (downloaded, result, metrics.snapshot().remove(1))
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_max_delay() {
+        let backoff = Backoff::exponential(Duration::from_millis(10))
+            .max_delay(Duration::from_millis(50))
+            .without_jitter();
+
+        let delays: Vec<_> = (0..5)
+            .map(|retry| backoff.delay(retry, &mut backoff.rng()).as_millis())
+            .collect();
+
+        assert_eq!(delays, [10, 20, 40, 50, 50]);
+    }
+
+    #[test]
+    fn jitter_keeps_the_delay_in_its_upper_half() {
+        let backoff = Backoff::exponential(Duration::from_millis(100));
+        let mut rng = backoff.rng();
+
+        for _ in 0..100 {
+            let delay = backoff.delay(1, &mut rng);
+            assert!(
+                (Duration::from_millis(100)..=Duration::from_millis(200)).contains(&delay),
+                "{delay:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn seeded_jitter_repeats_its_delays() {
+        let delays = |backoff: Backoff| {
+            let mut rng = backoff.rng();
+            (0..10)
+                .map(|_| backoff.delay(1, &mut rng))
+                .collect::<Vec<_>>()
+        };
+        let backoff = Backoff::exponential(Duration::from_millis(100));
+
+        assert_eq!(delays(backoff.seed(7)), delays(backoff.seed(7)));
+        assert_ne!(delays(backoff.seed(7)), delays(backoff.seed(8)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_recovers_flaky_downloads() {
+        let downloader = FlakyDownloader::new(7, 0.3);
+        let backoff = Backoff::exponential(Duration::from_millis(10)).retries(10);
+
+        let (downloaded, result, stage) = download_all(
+            &downloader,
+            Concurrency::concurrent_unordered(4),
+            ErrorPolicy::retry(backoff),
+        )
+        .await;
+
+        result.unwrap();
+        assert_eq!(downloaded.len(), 50);
+        assert!(downloader.failures() > 0);
+        assert_eq!(stage.retries, downloader.failures());
+        assert_eq!(stage.failures, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retried_items_keep_their_place_in_ordered_stages() {
+        let downloader = FlakyDownloader::new(11, 0.3);
+        let backoff = Backoff::exponential(Duration::from_millis(10)).retries(10);
+
+        let (downloaded, result, _) = download_all(
+            &downloader,
+            Concurrency::concurrent_ordered(8),
+            ErrorPolicy::retry(backoff),
+        )
+        .await;
+
+        result.unwrap();
+        assert!(downloader.failures() > 0);
+        assert_eq!(downloaded, urls(50));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drop_skips_failed_items() {
+        let downloader = FlakyDownloader::new(7, 0.3);
+
+        let (downloaded, result, stage) = download_all(
+            &downloader,
+            Concurrency::concurrent_unordered(4),
+            ErrorPolicy::drop(),
+        )
+        .await;
+
+        result.unwrap();
+        assert!(downloader.failures() > 0);
+        assert_eq!(downloaded.len() as u64, 50 - downloader.failures());
+        assert_eq!(stage.failures, downloader.failures());
+        assert_eq!(stage.retries, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn dead_letter_receives_items_that_ran_out_of_retries() {
+        let downloader = FlakyDownloader::new(3, 0.6);
+        let (dead_letter_sender, mut dead_letters) = mpsc::channel(50);
+        let backoff = Backoff::exponential(Duration::from_millis(10)).retries(2);
+
+        let (downloaded, result, stage) = download_all(
+            &downloader,
+            Concurrency::concurrent_unordered(4),
+            ErrorPolicy::dead_letter(dead_letter_sender).with_retry(backoff),
+        )
+        .await;
+        result.unwrap();
+
+        let mut failed = Vec::new();
+        while let Ok(dead_letter) = dead_letters.try_recv() {
+            assert_eq!(dead_letter.attempts, 3);
+            assert_eq!(dead_letter.error, DownloadError(dead_letter.item.clone()));
+            failed.push(dead_letter.item);
+        }
+
+        assert!(!failed.is_empty());
+        assert_eq!(stage.failures, failed.len() as u64);
+        let mut every_url = [downloaded, failed].concat();
+        every_url.sort_by_key(|url| url.rsplit('/').next().unwrap().parse::<u32>().unwrap());
+        assert_eq!(every_url, urls(50));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn abort_stops_the_pipeline() {
+        let downloader = FlakyDownloader::new(7, 0.3);
+
+        let (downloaded, result, stage) =
+            download_all(&downloader, Concurrency::serial(), ErrorPolicy::abort()).await;
+
+        let Err(PipelineError::Aborted { stage: name, error }) = result else {
+            panic!("the pipeline should abort, got {result:?}");
+        };
+        assert_eq!(name, "download");
+        assert_eq!(
+            error,
+            format!(
+                "failed to download https://example.com/image/{}",
+                downloaded.len()
+            )
+        );
+        assert_eq!(downloader.failures(), 1);
+        assert_eq!(stage.failures, 1);
+        assert!(stage.finished);
+    }
+}
@@ -0,0 +1,284 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures::{ready, stream::FuturesUnordered, Stream, StreamExt};
+
+/// What happened when polling the futures of a stage
+pub(crate) enum Progress<T> {
+    /// A future finished and its output can be sent right away
+    Done(T),
+    /// A future finished before an earlier one, its output waits in the reorder buffer
+    Buffered,
+    /// An output that waited in the reorder buffer can be sent now
+    Released(T),
+}
+
+/// A future tagged with the position of its item in the stage's input
+struct Sequenced<Fut> {
+    seq: u64,
+    future: Pin<Box<Fut>>,
+}
+
+impl<Fut: Future> Future for Sequenced<Fut> {
+    type Output = (u64, Fut::Output);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let seq = self.seq;
+        self.future.as_mut().poll(cx).map(|output| (seq, output))
+    }
+}
+
+/// Runs futures concurrently and yields their outputs in the order they were pushed.
+///
+/// Outputs that finish before an earlier one wait in a reorder buffer, with a slot for every
+/// item between the oldest one not yielded yet and the newest one pushed. The buffer holds at
+/// most `window` slots, so a slow item can't make the stage take an unbounded number of items
+pub(crate) struct Ordered<Fut: Future> {
+    running: FuturesUnordered<Sequenced<Fut>>,
+    /// The slot of the oldest item not yielded yet comes first, it's `None` while still running
+    buffer: VecDeque<Option<Fut::Output>>,
+    next_seq: u64,
+    window: usize,
+}
+
+impl<Fut: Future> Ordered<Fut> {
+    pub(crate) fn new(window: usize) -> Self {
+        Ordered {
+            running: FuturesUnordered::new(),
+            buffer: VecDeque::with_capacity(window),
+            next_seq: 0,
+            window,
+        }
+    }
+
+    pub(crate) fn push(&mut self, future: Fut) {
+        self.running.push(Sequenced {
+            seq: self.next_seq,
+            future: Box::pin(future),
+        });
+        self.buffer.push_back(None);
+        self.next_seq += 1;
+    }
+
+    pub(crate) fn running(&self) -> usize {
+        self.running.len()
+    }
+
+    /// Items pushed but not yielded yet, running or waiting for an earlier one
+    pub(crate) fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Whether another item fits in the reorder buffer
+    pub(crate) fn has_room(&self) -> bool {
+        self.buffer.len() < self.window
+    }
+
+    /// The sequence number of the first slot of the buffer
+    fn oldest_seq(&self) -> u64 {
+        self.next_seq - self.buffer.len() as u64
+    }
+
+    /// Like `poll_next`, but also tells apart outputs that just finished from outputs that waited
+    /// in the buffer, and returns `Progress::Buffered` when an output has to wait. That frees
+    /// a running slot, so the stage can take another item even though it has nothing to send yet
+    pub(crate) fn poll_progress(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Progress<Fut::Output>>> {
+        if let Some(Some(_)) = self.buffer.front() {
+            let output = self.buffer.pop_front().flatten();
+            return Poll::Ready(output.map(Progress::Released));
+        }
+
+        match self.running.poll_next_unpin(cx) {
+            Poll::Ready(Some((seq, output))) => {
+                let slot = (seq - self.oldest_seq()) as usize;
+                if slot == 0 {
+                    self.buffer.pop_front();
+                    return Poll::Ready(Some(Progress::Done(output)));
+                }
+                self.buffer[slot] = Some(output);
+                Poll::Ready(Some(Progress::Buffered))
+            }
+            // nothing is running, so nothing waits in the buffer either
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+// the futures are boxed and the buffered outputs are never pinned
+impl<Fut: Future> Unpin for Ordered<Fut> {}
+
+impl<Fut: Future> Stream for Ordered<Fut> {
+    type Item = Fut::Output;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            match ready!(this.poll_progress(cx)) {
+                Some(Progress::Done(output) | Progress::Released(output)) => {
+                    return Poll::Ready(Some(output))
+                }
+                Some(Progress::Buffered) => continue,
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use tokio::time::sleep;
+
+    use crate::{Concurrency, Pipeline};
+
+    use super::*;
+
+    async fn run_ordered(concurrency: Concurrency, delays: Vec<Duration>) -> Vec<usize> {
+        let n = delays.len();
+        let (mut output, handle) = Pipeline::from_iter(0..n)
+            .map(
+                move |i| {
+                    let delay = delays[i];
+                    async move {
+                        sleep(delay).await;
+                        i
+                    }
+                },
+                concurrency,
+            )
+            .build();
+
+        let mut items = Vec::new();
+        while let Some(item) = output.recv().await {
+            items.push(item);
+        }
+        handle.await.unwrap();
+        items
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn outputs_keep_the_input_order_under_random_latencies() {
+        let mut rng = StdRng::seed_from_u64(42);
+
+        for (limit, window) in [(1, 1), (2, 2), (4, 4), (4, 16), (16, 64), (32, 32)] {
+            let delays = (0..200)
+                .map(|_| Duration::from_millis(rng.gen_range(1..50)))
+                .collect();
+
+            let items = run_ordered(
+                Concurrency::concurrent_ordered(limit).reorder_window(window),
+                delays,
+            )
+            .await;
+
+            assert_eq!(
+                items,
+                (0..200).collect::<Vec<_>>(),
+                "limit {limit}, window {window}"
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_slow_item_holds_back_at_most_a_window_of_items() {
+        let (mut output, handle) = Pipeline::from_iter(0..100)
+            .map(
+                |i| async move {
+                    let delay = if i == 0 { 1000 } else { 1 };
+                    sleep(Duration::from_millis(delay)).await;
+                    i
+                },
+                Concurrency::concurrent_ordered(4).reorder_window(10),
+            )
+            .build();
+        let metrics = handle.metrics();
+
+        sleep(Duration::from_millis(100)).await;
+        let stage = &metrics.snapshot()[1];
+        assert_eq!(stage.items_in, 10, "{stage:?}");
+        assert_eq!(stage.items_out, 0, "{stage:?}");
+        // the buffered results are done, only the slow item is still worked on
+        assert_eq!(stage.in_flight, 1, "{stage:?}");
+
+        let mut items = Vec::new();
+        while let Some(item) = output.recv().await {
+            items.push(item);
+        }
+        handle.await.unwrap();
+        assert_eq!(items, (0..100).collect::<Vec<_>>());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_window_doesnt_raise_the_concurrency_limit() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let mut rng = StdRng::seed_from_u64(7);
+        let delays: Vec<_> = (0..100).map(|_| rng.gen_range(1..20)).collect();
+
+        let (mut output, handle) = Pipeline::from_iter(0..100)
+            .map(
+                {
+                    let (running, max_running) = (running.clone(), max_running.clone());
+                    move |i: usize| {
+                        let (running, max_running) = (running.clone(), max_running.clone());
+                        let delay = Duration::from_millis(delays[i]);
+                        async move {
+                            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                            max_running.fetch_max(now, Ordering::SeqCst);
+                            sleep(delay).await;
+                            running.fetch_sub(1, Ordering::SeqCst);
+                            i
+                        }
+                    }
+                },
+                Concurrency::concurrent_ordered(3).reorder_window(30),
+            )
+            .build();
+
+        let mut items = Vec::new();
+        while let Some(item) = output.recv().await {
+            items.push(item);
+        }
+        handle.await.unwrap();
+
+        assert_eq!(items, (0..100).collect::<Vec<_>>());
+        assert_eq!(max_running.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn ordered_yields_in_push_order() {
+        let mut ordered = Ordered::new(3);
+        let (first, second, third) = (
+            tokio::sync::oneshot::channel(),
+            tokio::sync::oneshot::channel(),
+            tokio::sync::oneshot::channel(),
+        );
+        ordered.push(first.1);
+        ordered.push(second.1);
+        ordered.push(third.1);
+        assert!(!ordered.has_room());
+
+        third.0.send(3).unwrap();
+        second.0.send(2).unwrap();
+        first.0.send(1).unwrap();
+
+        let outputs: Vec<_> = ordered.map(Result::unwrap).collect().await;
+        assert_eq!(outputs, [1, 2, 3]);
+    }
+}
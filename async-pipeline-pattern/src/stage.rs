@@ -0,0 +1,230 @@
+use std::{
+    fmt::Display,
+    future::{poll_fn, Future},
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use tokio::sync::mpsc::{Receiver, Sender};
+
+use crate::{
+    error::Aborted,
+    metrics::StageMetrics,
+    reorder::{Ordered, Progress},
+    Concurrency, ErrorPolicy,
+};
+
+/// What a stage does with an item once it's done with it
+pub(crate) enum Outcome<T> {
+    Send(T),
+    /// The item failed and the error policy dropped it, or sent it to the dead letter channel
+    Skip,
+    Abort(String),
+}
+
+/// The futures a stage is currently working on
+trait InFlight<Fut: Future> {
+    fn push(&mut self, future: Fut);
+
+    /// The next finished future or output to send, `None` once nothing is in flight
+    fn poll_progress(&mut self, cx: &mut Context<'_>) -> Poll<Option<Progress<Fut::Output>>>;
+
+    /// Whether the stage can take another item
+    fn has_room(&self, limit: usize) -> bool;
+
+    /// Whether nothing is running or waiting to be yielded
+    fn is_empty(&self) -> bool;
+}
+
+impl<Fut: Future> InFlight<Fut> for FuturesUnordered<Fut> {
+    fn push(&mut self, future: Fut) {
+        FuturesUnordered::push(self, future);
+    }
+
+    fn poll_progress(&mut self, cx: &mut Context<'_>) -> Poll<Option<Progress<Fut::Output>>> {
+        self.poll_next_unpin(cx)
+            .map(|output| output.map(Progress::Done))
+    }
+
+    fn has_room(&self, limit: usize) -> bool {
+        self.len() < limit
+    }
+
+    fn is_empty(&self) -> bool {
+        FuturesUnordered::is_empty(self)
+    }
+}
+
+impl<Fut: Future> InFlight<Fut> for Ordered<Fut> {
+    fn push(&mut self, future: Fut) {
+        Ordered::push(self, future);
+    }
+
+    fn poll_progress(&mut self, cx: &mut Context<'_>) -> Poll<Option<Progress<Fut::Output>>> {
+        Ordered::poll_progress(self, cx)
+    }
+
+    fn has_room(&self, limit: usize) -> bool {
+        self.running() < limit && Ordered::has_room(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Runs `f` on every item of `input` and sends the results to `output`.
+/// Returns once the input is closed and drained, or once the output receiver is dropped
+pub(crate) async fn map<In, Out, F, Fut>(
+    input: Receiver<In>,
+    output: Sender<Out>,
+    mut f: F,
+    concurrency: Concurrency,
+    metrics: Arc<StageMetrics>,
+) -> Result<(), Aborted>
+where
+    F: FnMut(In) -> Fut,
+    Fut: Future<Output = Out>,
+{
+    let f = move |item| f(item).map(Outcome::Send);
+    process(input, output, f, concurrency, metrics).await
+}
+
+/// Like `map`, with `policy` handling the items `f` fails on.
+/// Every item gets its own clone of `f`, so it can be retried without holding up the other items
+pub(crate) async fn try_map<In, Out, E, F, Fut>(
+    input: Receiver<In>,
+    output: Sender<Out>,
+    f: F,
+    concurrency: Concurrency,
+    policy: ErrorPolicy<In, E>,
+    metrics: Arc<StageMetrics>,
+) -> Result<(), Aborted>
+where
+    In: Clone,
+    E: Display,
+    F: FnMut(In) -> Fut + Clone,
+    Fut: Future<Output = Result<Out, E>>,
+{
+    let item_metrics = metrics.clone();
+    let f = move |item| {
+        let (f, policy, metrics) = (f.clone(), policy.clone(), item_metrics.clone());
+        async move { policy.call(item, f, &metrics).await }
+    };
+    process(input, output, f, concurrency, metrics).await
+}
+
+async fn process<In, Out, F, Fut>(
+    input: Receiver<In>,
+    output: Sender<Out>,
+    f: F,
+    concurrency: Concurrency,
+    metrics: Arc<StageMetrics>,
+) -> Result<(), Aborted>
+where
+    F: FnMut(In) -> Fut,
+    Fut: Future<Output = Outcome<Out>>,
+{
+    let limit = concurrency.limit;
+    // a single item in flight finishes in order anyway, no need for a reorder buffer
+    if concurrency.ordered && limit > 1 {
+        let in_flight = Ordered::new(concurrency.reorder_window);
+        run(input, output, f, limit, in_flight, &metrics).await
+    } else {
+        run(input, output, f, limit, FuturesUnordered::new(), &metrics).await
+    }
+}
+
+async fn run<In, Out, F, Fut, Q>(
+    mut input: Receiver<In>,
+    output: Sender<Out>,
+    mut f: F,
+    limit: usize,
+    mut in_flight: Q,
+    metrics: &StageMetrics,
+) -> Result<(), Aborted>
+where
+    F: FnMut(In) -> Fut,
+    Fut: Future<Output = Outcome<Out>>,
+    Q: InFlight<Fut>,
+{
+    loop {
+        let has_room = in_flight.has_room(limit);
+        let is_empty = in_flight.is_empty();
+
+        tokio::select! {
+            biased;
+
+            Some(item) = input.recv(), if has_room => {
+                metrics.received();
+                in_flight.push(f(item));
+            },
+            Some(progress) = poll_fn(|cx| in_flight.poll_progress(cx)), if !is_empty => {
+                let outcome = match progress {
+                    Progress::Done(outcome) => {
+                        metrics.completed();
+                        outcome
+                    }
+                    // an ordered stage buffered an output, check again for room to take an item
+                    Progress::Buffered => {
+                        metrics.completed();
+                        continue;
+                    }
+                    Progress::Released(outcome) => outcome,
+                };
+                match outcome {
+                    Outcome::Send(result) => {
+                        if output.send(result).await.is_err() {
+                            break;
+                        }
+                        metrics.sent();
+                    }
+                    Outcome::Skip => {}
+                    // dropping the channels stops the stages before this one, the ones after it drain
+                    Outcome::Abort(error) => return Err(Aborted(error)),
+                }
+            },
+            else => break
+        }
+    }
+    Ok(())
+}
+
+/// Forwards `input` to `output`, used to put a bigger channel between two stages,
+/// and as the source of a pipeline built from a receiver
+pub(crate) async fn forward<T>(
+    mut input: Receiver<T>,
+    output: Sender<T>,
+    metrics: Arc<StageMetrics>,
+) -> Result<(), Aborted> {
+    while let Some(item) = input.recv().await {
+        metrics.received();
+        metrics.completed();
+        if output.send(item).await.is_err() {
+            break;
+        }
+        metrics.sent();
+    }
+    Ok(())
+}
+
+/// Sends the items of `iter` to `output`, the source of a pipeline built from an iterator
+pub(crate) async fn source<I>(
+    iter: I,
+    output: Sender<I::Item>,
+    metrics: Arc<StageMetrics>,
+) -> Result<(), Aborted>
+where
+    I: Iterator,
+{
+    for item in iter {
+        metrics.received();
+        metrics.completed();
+        if output.send(item).await.is_err() {
+            break;
+        }
+        metrics.sent();
+    }
+    Ok(())
+}
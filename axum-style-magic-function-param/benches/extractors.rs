@@ -0,0 +1,65 @@
+//! Compares cloning extractors with borrowing ones, run with `cargo bench --bench extractors`
+
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use rust_magic_fn::{block_on, trigger, Context, FromContext, FromContextRef, Param};
+
+const ITERATIONS: u32 = 2_000;
+
+/// Runs `f` `ITERATIONS` times, returning the mean time of a run
+fn bench(name: &str, mut f: impl FnMut()) -> Duration {
+    // warm up
+    for _ in 0..ITERATIONS / 10 {
+        f();
+    }
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    let mean = start.elapsed() / ITERATIONS;
+
+    println!("{name:<40} {mean:>12?}");
+    mean
+}
+
+async fn cloning(Param(a): Param, Param(b): Param, Param(c): Param) -> u32 {
+    (a.len() + b.len() + c.len()) as u32
+}
+
+async fn borrowing(a: &str, b: &str, c: &str) -> u32 {
+    (a.len() + b.len() + c.len()) as u32
+}
+
+fn main() {
+    for size in [16, 1024, 1024 * 1024] {
+        println!("param of {size} bytes");
+        let context = Context::new("x".repeat(size), 33);
+
+        let cloned = bench("  Param::from_context", || {
+            black_box(Param::from_context(black_box(&context)).ok());
+        });
+        let borrowed = bench("  <&str>::from_context_ref", || {
+            black_box(<&str as FromContextRef>::from_context_ref(black_box(&context)).ok());
+        });
+        println!(
+            "  extraction speedup {:.1}x",
+            cloned.as_secs_f64() / borrowed.as_secs_f64()
+        );
+
+        // both handlers pay for cloning the context trigger consumes, the difference is the three param clones
+        let cloned = bench("  trigger(cloning)", || {
+            black_box(block_on(trigger(context.clone(), cloning)));
+        });
+        let borrowed = bench("  trigger(borrowing)", || {
+            black_box(block_on(trigger(context.clone(), borrowing)));
+        });
+        println!(
+            "  handler speedup {:.1}x\n",
+            cloned.as_secs_f64() / borrowed.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use quote::{quote, quote_spanned};
+use syn::{
+    parse::{Parse, ParseStream},
+    spanned::Spanned,
+    FnArg, GenericArgument, ItemFn, PathArguments, ReturnType, Token, Type,
+};
+
+/// The largest number of arguments the generated `Handler` implementations support
+const MAX_ARGS: usize = 16;
+
+/// The optional `state = T` attribute argument
+pub(crate) struct Args {
+    state: Option<Type>,
+}
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.is_empty() {
+            return Ok(Args { state: None });
+        }
+
+        let key: syn::Ident = input.parse()?;
+        if key != "state" {
+            return Err(syn::Error::new_spanned(key, "expected `state = Type`"));
+        }
+        input.parse::<Token![=]>()?;
+
+        Ok(Args {
+            state: Some(input.parse()?),
+        })
+    }
+}
+
+pub(crate) fn expand(args: Args, mut item: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let sig = &item.sig;
+
+    if sig.asyncness.is_none() {
+        return Err(syn::Error::new_spanned(
+            sig.fn_token,
+            "handlers must be `async` functions",
+        ));
+    }
+
+    // the checks live in nested functions, which can't name the generics of the handler
+    if !sig.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &sig.generics,
+            "generic handlers are not supported, elide lifetimes like `&str` or `ParamRef<'_>`",
+        ));
+    }
+
+    if sig.inputs.len() > MAX_ARGS {
+        return Err(syn::Error::new_spanned(
+            &sig.inputs,
+            format!("handlers can take at most {MAX_ARGS} arguments"),
+        ));
+    }
+
+    let state = match args.state {
+        Some(state) => quote!(#state),
+        None => quote!(()),
+    };
+
+    let mut types = Vec::new();
+    for input in &sig.inputs {
+        match input {
+            FnArg::Typed(arg) => types.push(&*arg.ty),
+            FnArg::Receiver(receiver) => {
+                return Err(syn::Error::new_spanned(
+                    receiver,
+                    "handlers can't take `self`",
+                ))
+            }
+        }
+    }
+
+    // a single borrowing argument makes it a borrowing handler, where every argument borrows
+    let borrowing = types.iter().any(|ty| borrows(ty));
+    let mut checks = Vec::new();
+    let last = types.len().saturating_sub(1);
+
+    for (i, ty) in types.into_iter().enumerate() {
+        // the span makes the compiler point at the offending argument type
+        checks.push(if borrowing {
+            quote_spanned! {ty.span()=>
+                {
+                    fn check<T>() where T: ::rust_magic_fn::FromContextRef<#state> {}
+                    check::<#ty>();
+                }
+            }
+        } else if i == last {
+            quote_spanned! {ty.span()=>
+                {
+                    fn check<T, M>() where T: ::rust_magic_fn::FromContextOnce<#state, M> {}
+                    check::<#ty, _>();
+                }
+            }
+        } else {
+            quote_spanned! {ty.span()=>
+                {
+                    fn check<T>() where T: ::rust_magic_fn::AsyncFromContext<#state> {}
+                    check::<#ty>();
+                }
+            }
+        });
+    }
+
+    if let ReturnType::Type(_, ty) = &sig.output {
+        checks.push(quote_spanned! {ty.span()=>
+            {
+                fn check<T>() where T: ::rust_magic_fn::IntoOutput {}
+                check::<#ty>();
+            }
+        });
+    }
+
+    let block = &item.block;
+    item.block = syn::parse_quote!({
+        #(#checks)*
+        #block
+    });
+
+    Ok(quote!(#item))
+}
+
+/// Whether the argument type is a borrowing extractor: a reference, a type with a lifetime like
+/// `ParamRef<'_>`, or one of the borrowing extractors, which may also be written without a lifetime
+fn borrows(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(_) => true,
+        Type::Paren(paren) => borrows(&paren.elem),
+        Type::Group(group) => borrows(&group.elem),
+        Type::Path(path) => path.path.segments.iter().any(|segment| {
+            if segment.ident == "Owned" || segment.ident == "ParamRef" {
+                return true;
+            }
+
+            match &segment.arguments {
+                PathArguments::AngleBracketed(args) => args.args.iter().any(|arg| match arg {
+                    GenericArgument::Lifetime(_) => true,
+                    GenericArgument::Type(ty) => borrows(ty),
+                    _ => false,
+                }),
+                _ => false,
+            }
+        }),
+        _ => false,
+    }
+}
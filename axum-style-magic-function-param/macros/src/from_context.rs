@@ -0,0 +1,89 @@
+use quote::{quote, quote_spanned};
+use syn::{spanned::Spanned, Data, DeriveInput, Fields, Type};
+
+pub(crate) fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "`FromContext` can only be derived for structs",
+        ));
+    };
+
+    let state = state_type(&input)?;
+    let ident = &input.ident;
+    let (_, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    // a concrete state replaces the generic `S` state parameter
+    let mut impl_generics = input.generics.clone();
+    let state = match state {
+        Some(state) => quote!(#state),
+        None => {
+            if let Some(param) = input.generics.type_params().find(|p| p.ident == "S") {
+                return Err(syn::Error::new_spanned(
+                    param,
+                    "`S` is reserved for the context state, rename this type parameter",
+                ));
+            }
+
+            impl_generics.params.push(syn::parse_quote!(S));
+            quote!(S)
+        }
+    };
+    let (impl_generics, _, _) = impl_generics.split_for_impl();
+
+    // each field is extracted on its own, the span points errors at the offending field type
+    let extract = |ty: &Type| {
+        quote_spanned! {ty.span()=>
+            <#ty as ::rust_magic_fn::FromContext<#state>>::from_context(context)?
+        }
+    };
+
+    let body = match &data.fields {
+        Fields::Named(fields) => {
+            let fields = fields.named.iter().map(|field| {
+                let name = &field.ident;
+                let value = extract(&field.ty);
+                quote!(#name: #value)
+            });
+            quote!(#ident { #(#fields),* })
+        }
+        Fields::Unnamed(fields) => {
+            let fields = fields.unnamed.iter().map(|field| extract(&field.ty));
+            quote!(#ident ( #(#fields),* ))
+        }
+        Fields::Unit => quote!(#ident),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::rust_magic_fn::FromContext<#state> for #ident #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn from_context(
+                context: &::rust_magic_fn::Context<#state>,
+            ) -> ::std::result::Result<Self, ::rust_magic_fn::Rejection> {
+                ::std::result::Result::Ok(#body)
+            }
+        }
+    })
+}
+
+/// Reads the optional `#[from_context(state = T)]` attribute
+fn state_type(input: &DeriveInput) -> syn::Result<Option<Type>> {
+    let mut state = None;
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|a| a.path().is_ident("from_context"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("state") {
+                state = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `state = Type`"))
+            }
+        })?;
+    }
+
+    Ok(state)
+}
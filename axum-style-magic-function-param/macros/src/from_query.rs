@@ -0,0 +1,75 @@
+use quote::{quote, quote_spanned};
+use syn::{spanned::Spanned, Data, DeriveInput, Fields, GenericArgument, PathArguments, Type};
+
+pub(crate) fn expand(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "`FromQuery` can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "`FromQuery` can only be derived for structs",
+            ))
+        }
+    };
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = fields.iter().map(|field| {
+        let name = &field.ident;
+        let key = name.as_ref().map(|name| name.to_string());
+
+        let value = match option_inner(&field.ty) {
+            Some(inner) => quote_spanned! {field.ty.span()=>
+                query.optional_value::<#inner>(#key)?
+            },
+            None => {
+                let ty = &field.ty;
+                quote_spanned! {field.ty.span()=>
+                    query.value::<#ty>(#key)?
+                }
+            }
+        };
+
+        quote!(#name: #value)
+    });
+
+    Ok(quote! {
+        impl #impl_generics ::rust_magic_fn::FromQuery for #ident #ty_generics #where_clause {
+            fn from_query(
+                query: &::rust_magic_fn::QueryPairs,
+            ) -> ::std::result::Result<Self, ::rust_magic_fn::Rejection> {
+                ::std::result::Result::Ok(#ident { #(#fields),* })
+            }
+        }
+    })
+}
+
+/// Returns `T` for an `Option<T>` field type
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+
+    match args.args.first()? {
+        GenericArgument::Type(inner) => Some(inner),
+        _ => None,
+    }
+}
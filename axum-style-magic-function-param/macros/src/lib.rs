@@ -0,0 +1,53 @@
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput, ItemFn};
+
+mod debug_handler;
+mod from_context;
+mod from_query;
+
+/// Derives `FromContext` for a struct whose fields are all extractors, so a handler can take
+/// one aggregated argument instead of many.
+///
+/// The implementation is generic over the context state `S`. When a field needs a concrete state,
+/// e.g. `State<Counter>`, set it with `#[from_context(state = AppState)]`
+#[proc_macro_derive(FromContext, attributes(from_context))]
+pub fn derive_from_context(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    from_context::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `FromQuery` for a struct with named fields, so it can be extracted with `Query<T>`.
+///
+/// Every field is parsed with `FromStr` from the query value of the same name.
+/// `Option` fields are optional, any other missing field rejects the context
+#[proc_macro_derive(FromQuery)]
+pub fn derive_from_query(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    from_query::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Checks a handler at its definition, so a wrong argument or return type gets an error pointing at it
+/// instead of an opaque "`Handler<_>` is not implemented" error where the handler is used.
+///
+/// The checks assume the context state is `()`, set it with `#[debug_handler(state = AppState)]`.
+/// A handler with a borrowing argument, like `&str` or `Owned<T>`, has every argument checked as a
+/// borrowing extractor. Generic handlers are not supported
+#[proc_macro_attribute]
+pub fn debug_handler(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as debug_handler::Args);
+    let input = parse_macro_input!(input as ItemFn);
+
+    // keep the handler around on errors, so its uses don't pile up more errors
+    let mut output = quote::ToTokens::to_token_stream(&input);
+    if let Err(err) = debug_handler::expand(args, input).map(|checked| output = checked) {
+        output.extend(err.into_compile_error());
+    }
+
+    output.into()
+}
@@ -0,0 +1,37 @@
+use std::net::TcpListener;
+
+use rust_magic_fn::{serve_with, Body, CatchPanicLayer, Id, Param, Router, TIMEOUT};
+
+async fn hello() -> String {
+    "hello from a magic handler".into()
+}
+
+async fn whoami(Param(path): Param, Id(id): Id) -> String {
+    format!("you asked for {path} with id {id}")
+}
+
+async fn echo(Id(id): Id, Body(body): Body) -> String {
+    format!("{id}: {}", String::from_utf8_lossy(&body))
+}
+
+async fn divide(Id(id): Id) -> Result<u32, String> {
+    100u32.checked_div(id).ok_or("can't divide by id 0".into())
+}
+
+fn main() -> std::io::Result<()> {
+    let addr = std::env::args().nth(1).unwrap_or("127.0.0.1:3000".into());
+    let listener = TcpListener::bind(&addr)?;
+    println!("listening on http://{}", listener.local_addr()?);
+
+    let router = Router::new()
+        .route("/", hello)
+        .route("/whoami", whoami)
+        .route("/echo", echo)
+        .route("/divide", divide)
+        .fallback(|Param(path): Param| async move { format!("nothing at {path}, try /whoami") })
+        .layer(CatchPanicLayer::new());
+
+    serve_with(listener, &router, TIMEOUT, |err| {
+        eprintln!("failed handling connection: {err}")
+    })
+}
@@ -0,0 +1,274 @@
+use std::ops::Deref;
+
+use crate::{Context, ExtractorDescription, FromContext, Handler, IntoOutput, Output, Rejection};
+
+/// Extracts a handler argument that borrows from the context instead of cloning out of it.
+///
+/// Handlers taking borrowing extractors are called with a different lifetime every time,
+/// so the argument type depends on the lifetime of the context borrow - `Item<'a>`.
+/// `Self` only names the extractor, e.g. `&str` stands for `&'a str` for every `'a`
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can't be borrowed from the context",
+    label = "not a borrowing extractor",
+    note = "borrowing handlers take `&str`, `ParamRef`, `&[u8]` or `&Context`, wrap other extractors with `Owned`"
+)]
+pub trait FromContextRef<S = ()> {
+    type Item<'a>
+    where
+        S: 'a;
+
+    fn from_context_ref(context: &Context<S>) -> Result<Self::Item<'_>, Rejection>;
+
+    fn describe() -> ExtractorDescription;
+}
+
+/// The context param, borrowed
+impl<S> FromContextRef<S> for &str {
+    type Item<'a>
+        = &'a str
+    where
+        S: 'a;
+
+    fn from_context_ref(context: &Context<S>) -> Result<Self::Item<'_>, Rejection> {
+        Ok(context.param())
+    }
+
+    fn describe() -> ExtractorDescription {
+        ExtractorDescription::of::<&str>("param")
+    }
+}
+
+/// The context body, borrowed. Unlike `Body` it doesn't have to be the last argument
+impl<S> FromContextRef<S> for &[u8] {
+    type Item<'a>
+        = &'a [u8]
+    where
+        S: 'a;
+
+    fn from_context_ref(context: &Context<S>) -> Result<Self::Item<'_>, Rejection> {
+        Ok(context.body())
+    }
+
+    fn describe() -> ExtractorDescription {
+        ExtractorDescription::of::<&[u8]>("body")
+    }
+}
+
+/// The whole context, for anything the other extractors don't cover
+impl<S> FromContextRef<S> for &Context<S> {
+    type Item<'a>
+        = &'a Context<S>
+    where
+        S: 'a;
+
+    fn from_context_ref(context: &Context<S>) -> Result<Self::Item<'_>, Rejection> {
+        Ok(context)
+    }
+
+    fn describe() -> ExtractorDescription {
+        ExtractorDescription::of::<&Context<S>>("context")
+    }
+}
+
+/// `Param` without the clone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParamRef<'a>(pub &'a str);
+
+impl<S> FromContextRef<S> for ParamRef<'_> {
+    type Item<'a>
+        = ParamRef<'a>
+    where
+        S: 'a;
+
+    fn from_context_ref(context: &Context<S>) -> Result<Self::Item<'_>, Rejection> {
+        Ok(ParamRef(context.param()))
+    }
+
+    fn describe() -> ExtractorDescription {
+        ExtractorDescription::of::<ParamRef>("param")
+    }
+}
+
+impl Deref for ParamRef<'_> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0
+    }
+}
+
+/// Uses a regular `FromContext` extractor in a borrowing handler, e.g. `Owned(Id(id)): Owned<Id>`.
+///
+/// Regular extractors can't implement `FromContextRef` themselves, a handler taking only regular
+/// extractors would then match both `Handler` implementations and the compiler couldn't pick one
+pub struct Owned<T>(pub T);
+
+impl<S, T> FromContextRef<S> for Owned<T>
+where
+    T: FromContext<S>,
+{
+    type Item<'a>
+        = Owned<T>
+    where
+        S: 'a;
+
+    fn from_context_ref(context: &Context<S>) -> Result<Self::Item<'_>, Rejection> {
+        T::from_context(context).map(Owned)
+    }
+
+    fn describe() -> ExtractorDescription {
+        T::describe()
+    }
+}
+
+/// Tells the borrowing `Handler` implementations apart from the regular ones, which all use tuples
+pub struct Borrowed<T>(T);
+
+/// Implements `Handler` for async functions taking only borrowing extractors.
+///
+/// Like systems, a function taking `&str` implements `AsyncFnMut(T1)` for some `T1` which lets the
+/// compiler infer the extractors, and `AsyncFnMut(T1::Item<'a>)` for every `'a`, which lets us call it
+/// with arguments borrowed from a context that only lives as long as the call
+macro_rules! impl_borrowing_handler {
+    ([]) => {};
+    ([$($ty:ident),*], $last:ident) => {
+        impl_borrowing_handler!(@impl $($ty,)* $last);
+    };
+    (@impl $($ty:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<F, O, S, $($ty,)*> Handler<Borrowed<($($ty,)*)>, S> for F
+        where
+            F: AsyncFnMut($($ty),*) -> O + for<'a> AsyncFnMut($($ty::Item<'a>),*) -> O,
+            O: IntoOutput,
+            $($ty: FromContextRef<S>,)*
+        {
+            async fn call_mut(&mut self, context: Context<S>) -> Output {
+                // pins down which `AsyncFnMut` implementation to call, the compiler can't choose on its own
+                #[allow(clippy::too_many_arguments)]
+                async fn call<O, $($ty,)*>(f: &mut impl AsyncFnMut($($ty),*) -> O, $($ty: $ty,)*) -> O {
+                    f($($ty),*).await
+                }
+
+                $(
+                    let $ty = match $ty::from_context_ref(&context) {
+                        Ok(value) => value,
+                        Err(rejection) => return rejection.into_output(),
+                    };
+                )*
+                call(self, $($ty,)*).await.into_output()
+            }
+
+            fn describe() -> Vec<ExtractorDescription> {
+                vec![$($ty::describe(),)*]
+            }
+        }
+    };
+}
+
+all_the_tuples!(impl_borrowing_handler);
+
+#[cfg(test)]
+mod tests {
+    use crate::{block_on, trigger, trigger_mut, Id, Path, Router};
+
+    use super::*;
+
+    async fn greet(name: &str) -> String {
+        format!("hello {name}")
+    }
+
+    async fn shout(ParamRef(name): ParamRef<'_>, body: &[u8]) -> String {
+        format!("{} {}", name.to_uppercase(), body.len())
+    }
+
+    #[test]
+    fn str_borrows_the_param() {
+        let output = block_on(trigger(Context::new("alex".into(), 33), greet));
+
+        assert_eq!(output, Output::Text("hello alex".into()));
+    }
+
+    #[test]
+    fn borrowed_extractors_can_be_mixed() {
+        let context = Context::new("alex".into(), 33).with_body("abc");
+
+        assert_eq!(
+            block_on(trigger(context, shout)),
+            Output::Text("ALEX 3".into())
+        );
+    }
+
+    #[test]
+    fn owned_wraps_regular_extractors() {
+        async fn handler(name: &str, Owned(Id(id)): Owned<Id>, context: &Context) -> String {
+            format!("{name} {id} {}", context.id())
+        }
+
+        let output = block_on(trigger(Context::new("alex".into(), 33), handler));
+
+        assert_eq!(output, Output::Text("alex 33 33".into()));
+    }
+
+    #[crate::debug_handler]
+    async fn checked_borrowing_handler(
+        ParamRef(name): ParamRef<'_>,
+        body: &[u8],
+        Owned(Id(id)): Owned<Id>,
+    ) -> String {
+        format!("{name} {} {id}", body.len())
+    }
+
+    #[test]
+    fn debug_handler_checks_borrowing_handlers() {
+        let context = Context::new("alex".into(), 33).with_body("abc");
+
+        assert_eq!(
+            block_on(trigger(context, checked_borrowing_handler)),
+            Output::Text("alex 3 33".into())
+        );
+    }
+
+    #[test]
+    fn owned_extractor_rejections_are_the_output() {
+        async fn handler(_: &str, Owned(Path(id)): Owned<Path<u32>>) -> u32 {
+            id
+        }
+
+        let output = block_on(trigger(Context::new("alex".into(), 33), handler));
+
+        assert!(matches!(output, Output::Rejected(_)), "{output:?}");
+    }
+
+    #[test]
+    fn borrowing_closures_are_handlers() {
+        let output = block_on(trigger(
+            Context::new("alex".into(), 33),
+            async |name: &str| name.len() as u32,
+        ));
+
+        assert_eq!(output, Output::Number(4));
+    }
+
+    #[test]
+    fn borrowing_closures_can_mutate_captured_state() {
+        let mut seen = Vec::new();
+        let mut handler = async |name: &str| seen.push(name.to_string());
+
+        block_on(trigger_mut(Context::new("alex".into(), 33), &mut handler));
+        block_on(trigger_mut(Context::new("sam".into(), 33), &mut handler));
+
+        assert_eq!(seen, ["alex", "sam"]);
+    }
+
+    #[test]
+    fn borrowing_handlers_can_be_routed_and_described() {
+        let router = Router::new().route("/greet", greet).route("/shout", shout);
+
+        let output = block_on(router.dispatch("/greet", Context::new("alex".into(), 33)));
+
+        assert_eq!(output, Some(Output::Text("hello alex".into())));
+        assert!(router
+            .describe()
+            .contains(r#""name": "ParamRef", "source": "param""#));
+    }
+}
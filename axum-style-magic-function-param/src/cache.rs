@@ -0,0 +1,226 @@
+use std::sync::Mutex;
+
+use crate::{Context, Extensions, ExtractorDescription, FromContext, Rejection};
+
+/// The extractor values cached on a `Context`, one per extractor type.
+/// A clone of the context gets a copy of the values cached so far, `Context::with_state` starts over with an empty cache
+#[derive(Default)]
+pub(crate) struct ExtractorCache(Mutex<Extensions>);
+
+impl ExtractorCache {
+    fn get<T>(&self) -> Option<T>
+    where
+        T: Clone + 'static,
+    {
+        self.0.lock().unwrap().get::<T>().cloned()
+    }
+
+    fn insert<T>(&self, value: T)
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.0.lock().unwrap().insert(value);
+    }
+}
+
+impl Clone for ExtractorCache {
+    fn clone(&self) -> Self {
+        ExtractorCache(Mutex::new(self.0.lock().unwrap().clone()))
+    }
+}
+
+/// Runs the extractor `T` once per context, later extractions of `Cached<T>` get a clone of the first value.
+/// Useful for expensive extractors needed by several handler arguments, or by a middleware and the handler.
+/// Rejections are not cached, the next extraction tries again.
+///
+/// Only `FromContext` extractors can be cached - an `AsyncFromContext` implementation for `Cached`
+/// would conflict with the blanket one, since another crate could implement `FromContext` for `Cached`
+pub struct Cached<T>(pub T);
+
+impl<S, T> FromContext<S> for Cached<T>
+where
+    T: FromContext<S> + Clone + Send + Sync + 'static,
+{
+    fn from_context(context: &Context<S>) -> Result<Self, Rejection> {
+        if let Some(value) = context.cache().get::<T>() {
+            return Ok(Cached(value));
+        }
+
+        // the lock isn't held while extracting, the extractor may itself use `Cached`
+        let value = T::from_context(context)?;
+        context.cache().insert(value.clone());
+        Ok(Cached(value))
+    }
+
+    fn describe() -> ExtractorDescription {
+        T::describe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    use crate::{block_on, trigger, Handler, Id, Layer, Output, Router, Service};
+
+    use super::*;
+
+    /// Counts how many times the expensive extractors ran
+    type Calls = Arc<AtomicU32>;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct User(String);
+
+    impl FromContext<Calls> for User {
+        fn from_context(context: &Context<Calls>) -> Result<Self, Rejection> {
+            context.state().fetch_add(1, Ordering::SeqCst);
+            match context.id() {
+                0 => Err(Rejection::new("anonymous")),
+                id => Ok(User(format!("user {id}"))),
+            }
+        }
+    }
+
+    fn context(id: u32, calls: &Calls) -> Context<Calls> {
+        Context::new("magic".into(), id).with_state(calls.clone())
+    }
+
+    #[test]
+    fn cached_extractor_runs_once_per_context() {
+        let calls = Calls::default();
+
+        let output = block_on(trigger(
+            context(33, &calls),
+            |Cached(a): Cached<User>, Cached(b): Cached<User>, Cached(c): Cached<User>| async move {
+                assert_eq!(a, b);
+                assert_eq!(b, c);
+                a.0
+            },
+        ));
+
+        assert_eq!(output, Output::Text("user 33".into()));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn uncached_extractor_runs_every_time() {
+        let calls = Calls::default();
+
+        block_on(trigger(
+            context(33, &calls),
+            |_: User, _: User, _: User| async {},
+        ));
+
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn each_dispatch_has_its_own_cache() {
+        let calls = Calls::default();
+        let router =
+            Router::new().route("/user", |Cached(user): Cached<User>| async move { user.0 });
+
+        let first = block_on(router.dispatch("/user", context(1, &calls)));
+        let second = block_on(router.dispatch("/user", context(2, &calls)));
+
+        assert_eq!(first, Some(Output::Text("user 1".into())));
+        assert_eq!(second, Some(Output::Text("user 2".into())));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn rejections_are_not_cached() {
+        let calls = Calls::default();
+
+        let context = context(0, &calls);
+
+        for _ in 0..2 {
+            let rejection = Cached::<User>::from_context(&context).err();
+            assert_eq!(rejection, Some(Rejection::new("anonymous")));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    /// Rejects contexts without a user before calling the inner service
+    struct RequireUserLayer;
+
+    struct RequireUser<Svc>(Svc);
+
+    impl<Svc> Layer<Svc> for RequireUserLayer {
+        type Service = RequireUser<Svc>;
+
+        fn layer(&self, inner: Svc) -> Self::Service {
+            RequireUser(inner)
+        }
+    }
+
+    impl<Svc> Service for RequireUser<Svc>
+    where
+        Svc: Service<State = Calls>,
+    {
+        type State = Calls;
+
+        async fn call(&self, context: Context<Calls>) -> Output {
+            if let Err(rejection) = Cached::<User>::from_context(&context) {
+                return Output::Rejected(rejection);
+            }
+            self.0.call(context).await
+        }
+    }
+
+    #[test]
+    fn middleware_and_handler_share_the_cache() {
+        let calls = Calls::default();
+        let service =
+            (|Id(_): Id, Cached(user): Cached<User>| async move { user.0 }).layer(RequireUserLayer);
+
+        let output = block_on(service.call(context(33, &calls)));
+
+        assert_eq!(output, Output::Text("user 33".into()));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn cloned_context_keeps_cached_values() {
+        let calls = Calls::default();
+        let context = context(33, &calls);
+        Cached::<User>::from_context(&context).ok();
+
+        let cloned = context.clone();
+        let Ok(Cached(user)) = Cached::<User>::from_context(&cloned) else {
+            panic!("extraction should succeed");
+        };
+
+        assert_eq!(user, User("user 33".into()));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    /// Extracts the state it was created with
+    #[derive(Debug, Clone, PartialEq)]
+    struct Greeting(&'static str);
+
+    impl FromContext<&'static str> for Greeting {
+        fn from_context(context: &Context<&'static str>) -> Result<Self, Rejection> {
+            Ok(Greeting(context.state()))
+        }
+    }
+
+    #[test]
+    fn replacing_the_state_clears_the_cache() {
+        let context = Context::new("magic".into(), 33).with_state("hello");
+        let Ok(Cached(before)) = Cached::<Greeting>::from_context(&context) else {
+            panic!("extraction should succeed");
+        };
+
+        let context = context.with_state("goodbye");
+        let Ok(Cached(after)) = Cached::<Greeting>::from_context(&context) else {
+            panic!("extraction should succeed");
+        };
+
+        assert_eq!(before, Greeting("hello"));
+        assert_eq!(after, Greeting("goodbye"));
+    }
+}
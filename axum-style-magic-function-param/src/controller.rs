@@ -0,0 +1,294 @@
+use std::{future::Future, sync::Arc};
+
+use crate::{
+    AsyncFromContext, Context, ExtractorDescription, FromContextOnce, Handler, IntoOutput, Output,
+};
+
+/// A controller method bound to its controller, created by `with_self`
+pub struct WithSelf<C, F> {
+    controller: Arc<C>,
+    method: F,
+}
+
+impl<C, F> Clone for WithSelf<C, F>
+where
+    F: Clone,
+{
+    fn clone(&self) -> Self {
+        WithSelf {
+            controller: self.controller.clone(),
+            method: self.method.clone(),
+        }
+    }
+}
+
+/// Turns a method of `controller` into a handler, e.g. `with_self(users.clone(), UserController::show)`.
+/// The method takes `&self` or `self: Arc<Self>` followed by extractors, like any handler
+pub fn with_self<C, F>(controller: Arc<C>, method: F) -> WithSelf<C, F> {
+    WithSelf { controller, method }
+}
+
+/// A method taking a controller receiver followed by extractors, implemented for `&self` and
+/// `self: Arc<Self>` methods of every arity. `WithSelf` is a `Handler` for every `ControllerMethod`,
+/// a single implementation keeps compile errors about handlers short
+pub trait ControllerMethod<C, T, S = ()> {
+    fn call_method(
+        &mut self,
+        controller: &Arc<C>,
+        context: Context<S>,
+    ) -> impl Future<Output = Output>;
+
+    fn describe() -> Vec<ExtractorDescription>;
+}
+
+/// Tells the `WithSelf` `Handler` implementation apart from the others, which use tuples
+pub struct MethodArgs<T>(T);
+
+// not a useful hint when a function fails to be a handler
+#[diagnostic::do_not_recommend]
+impl<C, F, T, S> Handler<MethodArgs<T>, S> for WithSelf<C, F>
+where
+    F: ControllerMethod<C, T, S>,
+{
+    fn call_mut(&mut self, context: Context<S>) -> impl Future<Output = Output> {
+        self.method.call_method(&self.controller, context)
+    }
+
+    fn describe() -> Vec<ExtractorDescription> {
+        F::describe()
+    }
+}
+
+mod private {
+    // markers telling apart methods taking `&self` from methods taking `self: Arc<Self>`
+
+    #[derive(Debug, Clone, Copy)]
+    pub enum RefSelf {}
+
+    #[derive(Debug, Clone, Copy)]
+    pub enum ArcSelf {}
+}
+
+/// Implements `ControllerMethod` for every arity, once for each receiver.
+/// Extraction works like in `impl_handler`. The `&self` method borrows the controller for the
+/// length of the call, so its future depends on the borrow - the `AsyncFnMut` bound covers that
+macro_rules! impl_controller_method {
+    ([]) => {
+        impl<C, F, O, S> ControllerMethod<C, (private::RefSelf,), S> for F
+        where
+            F: for<'c> AsyncFnMut(&'c C) -> O,
+            O: IntoOutput,
+        {
+            async fn call_method(&mut self, controller: &Arc<C>, _context: Context<S>) -> Output {
+                self(controller).await.into_output()
+            }
+
+            fn describe() -> Vec<ExtractorDescription> {
+                Vec::new()
+            }
+        }
+
+        impl<C, F, O, S> ControllerMethod<C, (private::ArcSelf,), S> for F
+        where
+            F: AsyncFnMut(Arc<C>) -> O,
+            O: IntoOutput,
+        {
+            async fn call_method(&mut self, controller: &Arc<C>, _context: Context<S>) -> Output {
+                self(controller.clone()).await.into_output()
+            }
+
+            fn describe() -> Vec<ExtractorDescription> {
+                Vec::new()
+            }
+        }
+    };
+    ([$($ty:ident),*], $last:ident) => {
+        impl_controller_method!(
+            @impl private::RefSelf, [for<'c> AsyncFnMut(&'c C, $($ty,)* $last) -> O], |controller| &**controller;
+            [$($ty),*], $last
+        );
+        impl_controller_method!(
+            @impl private::ArcSelf, [AsyncFnMut(Arc<C>, $($ty,)* $last) -> O], |controller| controller.clone();
+            [$($ty),*], $last
+        );
+    };
+    (@impl $receiver:ty, [$($bound:tt)*], |$controller:ident| $receive:expr; [$($ty:ident),*], $last:ident) => {
+        #[allow(non_snake_case)]
+        impl<C, F, O, S, M, $($ty,)* $last> ControllerMethod<C, ($receiver, M, $($ty,)* $last,), S> for F
+        where
+            F: $($bound)*,
+            O: IntoOutput,
+            $($ty: AsyncFromContext<S>,)*
+            $last: FromContextOnce<S, M>,
+        {
+            async fn call_method(&mut self, $controller: &Arc<C>, context: Context<S>) -> Output {
+                $(
+                    let $ty = match $ty::from_context_async(&context).await {
+                        Ok(value) => value,
+                        Err(rejection) => return rejection.into_output(),
+                    };
+                )*
+                let $last = match $last::from_context_once(context).await {
+                    Ok(value) => value,
+                    Err(rejection) => return rejection.into_output(),
+                };
+
+                self($receive, $($ty,)* $last).await.into_output()
+            }
+
+            fn describe() -> Vec<ExtractorDescription> {
+                vec![
+                    $(<$ty as AsyncFromContext<S>>::describe(),)*
+                    <$last as FromContextOnce<S, M>>::describe(),
+                ]
+            }
+        }
+    };
+}
+
+all_the_tuples!(impl_controller_method);
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, sync::Mutex};
+
+    use crate::{block_on, trigger, Body, Id, Param, Path, Router};
+
+    use super::*;
+
+    /// Stands in for a database
+    #[derive(Default)]
+    struct UserRepository {
+        users: Mutex<BTreeMap<u32, String>>,
+    }
+
+    impl UserRepository {
+        fn insert(&self, name: String) -> u32 {
+            let mut users = self.users.lock().unwrap();
+            let id = users.len() as u32 + 1;
+            users.insert(id, name);
+            id
+        }
+
+        fn get(&self, id: u32) -> Option<String> {
+            self.users.lock().unwrap().get(&id).cloned()
+        }
+    }
+
+    #[derive(Default)]
+    struct UserController {
+        repository: UserRepository,
+    }
+
+    impl UserController {
+        async fn count(&self) -> u32 {
+            self.repository.users.lock().unwrap().len() as u32
+        }
+
+        async fn show(&self, Path(id): Path<u32>) -> Result<String, String> {
+            self.repository.get(id).ok_or(format!("no user {id}"))
+        }
+
+        async fn create(self: Arc<Self>, Body(name): Body) -> u32 {
+            self.repository
+                .insert(String::from_utf8_lossy(&name).into_owned())
+        }
+
+        async fn rename(&self, Id(id): Id, Param(name): Param) -> String {
+            let mut users = self.repository.users.lock().unwrap();
+            let previous = users.insert(id, name.clone()).unwrap_or_default();
+            format!("{previous} is now {name}")
+        }
+
+        async fn version(self: Arc<Self>) -> String {
+            "v1".into()
+        }
+    }
+
+    fn controller() -> Arc<UserController> {
+        let controller = Arc::new(UserController::default());
+        controller.repository.insert("alex".into());
+        controller
+    }
+
+    #[test]
+    fn ref_self_methods_are_handlers() {
+        let controller = controller();
+
+        let found = block_on(trigger(
+            Context::new("1".into(), 33),
+            with_self(controller.clone(), UserController::show),
+        ));
+        let missing = block_on(trigger(
+            Context::new("2".into(), 33),
+            with_self(controller.clone(), UserController::show),
+        ));
+        let count = block_on(trigger(
+            Context::new("magic".into(), 33),
+            with_self(controller, UserController::count),
+        ));
+
+        assert_eq!(found, Output::Text("alex".into()));
+        assert_eq!(missing, Output::Error("no user 2".into()));
+        assert_eq!(count, Output::Number(1));
+    }
+
+    #[test]
+    fn arc_self_methods_are_handlers() {
+        let controller = controller();
+
+        let id = block_on(trigger(
+            Context::new("magic".into(), 33).with_body("sam"),
+            with_self(controller.clone(), UserController::create),
+        ));
+        let version = block_on(trigger(
+            Context::new("magic".into(), 33),
+            with_self(controller.clone(), UserController::version),
+        ));
+
+        assert_eq!(id, Output::Number(2));
+        assert_eq!(version, Output::Text("v1".into()));
+        assert_eq!(controller.repository.get(2), Some("sam".into()));
+    }
+
+    #[test]
+    fn controller_methods_share_the_repository_through_the_router() {
+        let controller = controller();
+        let router = Router::new()
+            .route(
+                "/users/show",
+                with_self(controller.clone(), UserController::show),
+            )
+            .route(
+                "/users/create",
+                with_self(controller.clone(), UserController::create),
+            )
+            .route(
+                "/users/rename",
+                with_self(controller.clone(), UserController::rename),
+            );
+
+        let created = block_on(router.dispatch(
+            "/users/create",
+            Context::new("magic".into(), 33).with_body("sam"),
+        ));
+        let renamed = block_on(router.dispatch("/users/rename", Context::new("kim".into(), 2)));
+        let shown = block_on(router.dispatch("/users/show", Context::new("2".into(), 33)));
+
+        assert_eq!(created, Some(Output::Number(2)));
+        assert_eq!(renamed, Some(Output::Text("sam is now kim".into())));
+        assert_eq!(shown, Some(Output::Text("kim".into())));
+    }
+
+    #[test]
+    fn methods_describe_their_extractors_without_the_receiver() {
+        let router: Router =
+            Router::new().route("/rename", with_self(controller(), UserController::rename));
+
+        let description = router.describe();
+
+        assert!(description.contains(r#""name": "Id""#), "{description}");
+        assert!(description.contains(r#""name": "Param""#), "{description}");
+        assert!(!description.contains("UserController"), "{description}");
+    }
+}
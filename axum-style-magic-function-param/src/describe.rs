@@ -0,0 +1,83 @@
+use std::any::type_name;
+
+/// Describes one handler argument, see `FromContext::describe`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractorDescription {
+    /// The extractor name without its module path or generics, e.g. `Path`
+    pub name: &'static str,
+    /// Where the extractor gets its value from, `param`, `id`, `body`, `state` and `extensions`
+    /// for the parts of the context, `context` when the extractor doesn't say
+    pub source: &'static str,
+    /// The full extractor type, e.g. `rust_magic_fn::path::Path<u32>`
+    pub ty: &'static str,
+}
+
+impl ExtractorDescription {
+    /// Describes the extractor `T`, taking its name and type from `type_name`
+    pub fn of<T>(source: &'static str) -> Self {
+        let ty = type_name::<T>();
+        let path = ty.split('<').next().unwrap_or(ty);
+        let name = path.rsplit("::").next().unwrap_or(path);
+
+        ExtractorDescription { name, source, ty }
+    }
+
+    pub(crate) fn to_json(&self) -> String {
+        format!(
+            r#"{{ "name": {}, "source": {}, "type": {} }}"#,
+            json_string(self.name),
+            json_string(self.source),
+            json_string(self.ty)
+        )
+    }
+}
+
+/// Quotes and escapes `value` as a JSON string
+pub(crate) fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Path, Query, QueryPairs};
+
+    use super::*;
+
+    #[test]
+    fn name_drops_module_path_and_generics() {
+        let description = ExtractorDescription::of::<Query<QueryPairs>>("param");
+
+        assert_eq!(description.name, "Query");
+        assert_eq!(
+            description.ty,
+            "rust_magic_fn::query::Query<rust_magic_fn::query::QueryPairs>"
+        );
+    }
+
+    #[test]
+    fn to_json_has_every_field() {
+        assert_eq!(
+            ExtractorDescription::of::<Path<u32>>("param").to_json(),
+            r#"{ "name": "Path", "source": "param", "type": "rust_magic_fn::path::Path<u32>" }"#
+        );
+    }
+
+    #[test]
+    fn json_string_escapes() {
+        assert_eq!(json_string("a \"b\"\\\n\u{1}"), r#""a \"b\"\\\n\u0001""#);
+    }
+}
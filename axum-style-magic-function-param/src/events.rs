@@ -0,0 +1,233 @@
+use std::{
+    any::{type_name, TypeId},
+    collections::HashMap,
+};
+
+use crate::{Context, ExtractorDescription, FromContext, Handler, Output, Rejection, Route};
+
+/// Extracts the event being published, a handler taking `Event<E>` as its first argument
+/// is subscribed to events of type `E`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event<E>(pub E);
+
+impl<S, E> FromContext<S> for Event<E>
+where
+    E: Clone + 'static,
+{
+    fn from_context(context: &Context<S>) -> Result<Self, Rejection> {
+        context
+            .extensions()
+            .get::<Event<E>>()
+            .cloned()
+            .ok_or_else(|| Rejection::new(format!("missing event `{}`", type_name::<E>())))
+    }
+
+    fn describe() -> ExtractorDescription {
+        ExtractorDescription::of::<Self>("extensions")
+    }
+}
+
+/// Implemented for the argument tuples of handlers whose first argument is `Event<E>`,
+/// lets `EventBus::subscribe` find out which event a handler is interested in
+#[diagnostic::on_unimplemented(
+    message = "event handlers must take `Event<E>` as their first argument",
+    label = "not an event handler"
+)]
+pub trait EventArgs {
+    type Event;
+}
+
+/// Implements `EventArgs` for the `Handler` argument tuples `(M, Event<E>, T1, ..)`
+macro_rules! impl_event_args {
+    ([]) => {};
+    ([$($ty:ident),*], $last:ident) => {
+        impl<M, E, $($ty,)*> EventArgs for (M, Event<E>, $($ty,)*) {
+            type Event = E;
+        }
+    };
+}
+
+all_the_tuples!(impl_event_args);
+
+/// Calls the handlers subscribed to an event whenever it is published.
+///
+/// Handlers of an event run one after the other, in the order they subscribed,
+/// each one finishing before the next one starts
+pub struct EventBus<S = ()> {
+    state: S,
+    handlers: HashMap<TypeId, Vec<Route<S>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::with_state(())
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> EventBus<S>
+where
+    S: Clone + 'static,
+{
+    /// Creates a bus whose handlers can extract `State` from `state`
+    pub fn with_state(state: S) -> Self {
+        EventBus {
+            state,
+            handlers: HashMap::new(),
+        }
+    }
+
+    pub fn subscribe<T, H>(mut self, handler: H) -> Self
+    where
+        H: Handler<T, S> + Clone + 'static,
+        T: EventArgs + 'static,
+        T::Event: 'static,
+    {
+        self.handlers
+            .entry(TypeId::of::<T::Event>())
+            .or_default()
+            .push(Route::new(handler.into_service()));
+        self
+    }
+
+    /// Calls every handler subscribed to `E` with its own clone of `event`,
+    /// returns their outputs in subscription order
+    pub async fn publish<E>(&self, event: E) -> Vec<Output>
+    where
+        E: Clone + Send + Sync + 'static,
+    {
+        let Some(handlers) = self.handlers.get(&TypeId::of::<E>()) else {
+            return Vec::new();
+        };
+
+        let mut outputs = Vec::with_capacity(handlers.len());
+        for handler in handlers {
+            let mut context = Context::new(String::new(), 0).with_state(self.state.clone());
+            context.extensions_mut().insert(Event(event.clone()));
+            outputs.push(crate::Service::call(handler, context).await);
+        }
+        outputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::{block_on, Extension, State};
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct UserCreated(String);
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct OrderPlaced(u32);
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    async fn welcome(Event(UserCreated(name)): Event<UserCreated>) -> String {
+        format!("welcome {name}")
+    }
+
+    async fn ship(Event(OrderPlaced(order)): Event<OrderPlaced>) -> String {
+        format!("shipping order {order}")
+    }
+
+    #[test]
+    fn publish_calls_only_matching_handlers() {
+        let bus = EventBus::new().subscribe(welcome).subscribe(ship);
+
+        assert_eq!(
+            block_on(bus.publish(UserCreated("alex".into()))),
+            [Output::Text("welcome alex".into())]
+        );
+        assert_eq!(
+            block_on(bus.publish(OrderPlaced(7))),
+            [Output::Text("shipping order 7".into())]
+        );
+    }
+
+    #[test]
+    fn publish_without_subscribers_returns_nothing() {
+        let bus = EventBus::new().subscribe(welcome);
+
+        assert_eq!(block_on(bus.publish(OrderPlaced(7))), []);
+    }
+
+    #[test]
+    fn handlers_run_in_subscription_order() {
+        async fn first(Event(_): Event<UserCreated>, State(log): State<Log>) {
+            log.lock().unwrap().push("first".into());
+        }
+
+        async fn second(Event(_): Event<UserCreated>, State(log): State<Log>) {
+            log.lock().unwrap().push("second".into());
+        }
+
+        async fn third(Event(_): Event<UserCreated>, State(log): State<Log>) {
+            log.lock().unwrap().push("third".into());
+        }
+
+        let log = Log::default();
+        let bus = EventBus::with_state(log.clone())
+            .subscribe(second)
+            .subscribe(first)
+            .subscribe(third);
+
+        block_on(bus.publish(UserCreated("alex".into())));
+        block_on(bus.publish(UserCreated("alex".into())));
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["second", "first", "third", "second", "first", "third"]
+        );
+    }
+
+    #[test]
+    fn handlers_can_take_other_extractors() {
+        let log = Log::default();
+        let bus = EventBus::with_state(log.clone())
+            .subscribe(
+                |Event(UserCreated(name)): Event<UserCreated>, State(log): State<Log>| async move {
+                    log.lock().unwrap().push(format!("user {name}"));
+                },
+            )
+            .subscribe(
+                |Event(OrderPlaced(order)): Event<OrderPlaced>, State(log): State<Log>| async move {
+                    log.lock().unwrap().push(format!("order {order}"));
+                },
+            );
+
+        block_on(bus.publish(OrderPlaced(1)));
+        block_on(bus.publish(UserCreated("alex".into())));
+        block_on(bus.publish(OrderPlaced(2)));
+
+        assert_eq!(*log.lock().unwrap(), ["order 1", "user alex", "order 2"]);
+    }
+
+    #[test]
+    fn rejections_are_returned_as_output() {
+        async fn needs_extension(
+            Event(_): Event<OrderPlaced>,
+            Extension(user): Extension<String>,
+        ) -> String {
+            user
+        }
+
+        let bus = EventBus::new().subscribe(needs_extension).subscribe(ship);
+
+        assert_eq!(
+            block_on(bus.publish(OrderPlaced(7))),
+            [
+                Output::Rejected(Rejection::new("missing extension `alloc::string::String`")),
+                Output::Text("shipping order 7".into())
+            ]
+        );
+    }
+}
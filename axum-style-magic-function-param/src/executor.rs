@@ -0,0 +1,71 @@
+use std::{
+    future::Future,
+    pin::pin,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+};
+
+/// Wakes the thread that is blocked on the future
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// A tiny executor - polls the future on the current thread, parking it until the future is woken.
+/// Good enough to drive our handlers without pulling in a full runtime
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A future that returns `Pending` once before completing
+    pub(crate) struct YieldOnce(pub(crate) bool);
+
+    impl Future for YieldOnce {
+        type Output = u32;
+
+        fn poll(mut self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+            if self.0 {
+                return Poll::Ready(42);
+            }
+
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn block_on_drives_pending_future_to_completion() {
+        assert_eq!(block_on(YieldOnce(false)), 42);
+    }
+
+    #[test]
+    fn block_on_returns_future_output_from_other_thread() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let handle = thread::spawn(move || tx.send(7).unwrap());
+
+        let output = block_on(async move {
+            handle.join().unwrap();
+            rx.recv().unwrap()
+        });
+
+        assert_eq!(output, 7);
+    }
+}
@@ -0,0 +1,194 @@
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::HashMap,
+};
+
+use crate::{Context, ExtractorDescription, FromContext, Rejection};
+
+/// A map holding one value per type, letting middleware attach data to a `Context`
+/// without the `Context` knowing about it in advance
+#[derive(Clone, Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Box<dyn AnyClone + Send + Sync>>,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts a value, returning the previous value of the same type if there was one
+    pub fn insert<T>(&mut self, value: T) -> Option<T>
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|previous| previous.into_any().downcast().ok())
+            .map(|previous| *previous)
+    }
+
+    pub fn get<T>(&self) -> Option<&T>
+    where
+        T: 'static,
+    {
+        self.map
+            .get(&TypeId::of::<T>())
+            // deref past the box, it implements `AnyClone` too
+            .and_then(|value| (**value).as_any().downcast_ref())
+    }
+}
+
+/// `Any` that can be cloned, so `Extensions` and with it `Context` stay `Clone`
+trait AnyClone: Any {
+    fn clone_box(&self) -> Box<dyn AnyClone + Send + Sync>;
+    fn as_any(&self) -> &dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T> AnyClone for T
+where
+    T: Clone + Send + Sync + 'static,
+{
+    fn clone_box(&self) -> Box<dyn AnyClone + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Clone for Box<dyn AnyClone + Send + Sync> {
+    fn clone(&self) -> Self {
+        // deref to call `clone_box` on the value itself rather than on the box
+        (**self).clone_box()
+    }
+}
+
+/// Extracts a clone of the `T` stored in the context extensions,
+/// rejecting when there is no such value
+pub struct Extension<T>(pub T);
+
+impl<S, T> FromContext<S> for Extension<T>
+where
+    T: Clone + 'static,
+{
+    fn from_context(context: &Context<S>) -> Result<Self, Rejection> {
+        context
+            .extensions()
+            .get::<T>()
+            .map(|value| Extension(value.clone()))
+            .ok_or_else(|| Rejection::new(format!("missing extension `{}`", type_name::<T>())))
+    }
+
+    fn describe() -> ExtractorDescription {
+        ExtractorDescription::of::<Self>("extensions")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{block_on, trigger, Handler, Id, Layer, Output, Service, ServiceExt};
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct User(String);
+
+    #[test]
+    fn insert_returns_previous_value() {
+        let mut extensions = Extensions::new();
+
+        assert_eq!(extensions.insert(User("a".into())), None);
+        assert_eq!(extensions.insert(User("b".into())), Some(User("a".into())));
+        assert_eq!(extensions.get::<User>(), Some(&User("b".into())));
+    }
+
+    #[test]
+    fn get_returns_none_for_missing_type() {
+        let mut extensions = Extensions::new();
+        extensions.insert(33u32);
+
+        assert_eq!(extensions.get::<User>(), None);
+        assert_eq!(extensions.get::<u32>(), Some(&33));
+    }
+
+    #[test]
+    fn cloned_extensions_are_independent() {
+        let mut extensions = Extensions::new();
+        extensions.insert(User("a".into()));
+
+        let mut cloned = extensions.clone();
+        cloned.insert(User("b".into()));
+
+        assert_eq!(extensions.get::<User>(), Some(&User("a".into())));
+        assert_eq!(cloned.get::<User>(), Some(&User("b".into())));
+    }
+
+    #[test]
+    fn extension_rejects_when_missing() {
+        let output = block_on(trigger(
+            Context::new("magic".into(), 33),
+            |Extension(user): Extension<User>| async move { user.0 },
+        ));
+
+        assert!(matches!(
+            output,
+            Output::Rejected(rejection) if rejection.message().contains("User")
+        ));
+    }
+
+    /// Looks up the user by the context id and stores it for the handler
+    struct LoadUserLayer;
+
+    struct LoadUser<Svc>(Svc);
+
+    impl<Svc> Layer<Svc> for LoadUserLayer {
+        type Service = LoadUser<Svc>;
+
+        fn layer(&self, inner: Svc) -> Self::Service {
+            LoadUser(inner)
+        }
+    }
+
+    impl<Svc> Service for LoadUser<Svc>
+    where
+        Svc: Service,
+    {
+        type State = Svc::State;
+
+        async fn call(&self, mut context: Context<Self::State>) -> Output {
+            let user = User(format!("user-{}", context.id()));
+            context.extensions_mut().insert(user);
+            self.0.call(context).await
+        }
+    }
+
+    #[test]
+    fn extension_extracts_value_inserted_by_middleware() {
+        let service = (|Id(id): Id, Extension(user): Extension<User>| async move {
+            format!("{id} is {}", user.0)
+        })
+        .layer(LoadUserLayer);
+
+        let output = block_on(service.call(Context::new("magic".into(), 33)));
+
+        assert_eq!(output, Output::Text("33 is user-33".into()));
+    }
+
+    #[test]
+    fn extension_works_with_layered_service() {
+        let service = (|Extension(user): Extension<User>| async move { user.0 })
+            .into_service()
+            .layer(LoadUserLayer);
+
+        let output = block_on(service.call(Context::new("magic".into(), 7)));
+
+        assert_eq!(output, Output::Text("user-7".into()));
+    }
+}
@@ -0,0 +1,289 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+use crate::{block_on, Context, Output, Router};
+
+/// The header the `Id` extractor reads from
+pub const ID_HEADER: &str = "x-id";
+
+/// The largest request body read into a `Context`, bigger ones are rejected before allocating
+pub const MAX_BODY: usize = 1024 * 1024;
+
+/// How long `serve` waits on a client to send its request or take the response
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves `router` over HTTP/1.1, one connection at a time, until accepting a connection fails.
+///
+/// The request path is the route and the `Param`, the `X-Id` header is the `Id`
+/// and the request body is the `Body`. Every connection is closed after a single response.
+/// A client that stalls for longer than `TIMEOUT` is dropped, and failed connections are ignored,
+/// use `serve_with` to set the timeout or report them
+pub fn serve(listener: TcpListener, router: &Router) -> io::Result<()> {
+    serve_with(listener, router, TIMEOUT, |_| {})
+}
+
+/// `serve` with a timeout for reading the request and writing the response,
+/// calling `on_error` with the error of every failed connection
+pub fn serve_with(
+    listener: TcpListener,
+    router: &Router,
+    timeout: Duration,
+    mut on_error: impl FnMut(io::Error),
+) -> io::Result<()> {
+    for stream in listener.incoming() {
+        if let Err(err) = handle_connection(stream?, router, timeout) {
+            on_error(err);
+        }
+    }
+    Ok(())
+}
+
+fn handle_connection(mut stream: TcpStream, router: &Router, timeout: Duration) -> io::Result<()> {
+    // connections are served one at a time, a silent client must not hold up the others
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let response = match read_request(&mut BufReader::new(&stream)) {
+        Ok((path, context)) => match block_on(router.dispatch(&path, context)) {
+            Some(output) => Response::from(output),
+            None => Response::new(404, format!("no route for {path}")),
+        },
+        Err(err) if err.kind() == io::ErrorKind::InvalidData => Response::new(400, err.to_string()),
+        Err(err) => return Err(err),
+    };
+
+    stream.write_all(&response.to_bytes())?;
+    stream.flush()
+}
+
+/// Reads the request line, headers and body, returning the request path and its context
+fn read_request(reader: &mut impl BufRead) -> io::Result<(String, Context)> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut parts = request_line.split_whitespace();
+    let (Some(_method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid_data(format!(
+            "malformed request line `{}`",
+            request_line.trim_end()
+        )));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(invalid_data(format!("unsupported version `{version}`")));
+    }
+
+    let mut id = 0;
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data(
+                "connection closed before the end of the headers",
+            ));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(invalid_data(format!("malformed header `{line}`")));
+        };
+        let value = value.trim();
+        match name.trim().to_ascii_lowercase().as_str() {
+            ID_HEADER => {
+                id = value
+                    .parse()
+                    .map_err(|err| invalid_data(format!("invalid id `{value}`: {err}")))?
+            }
+            "content-length" => {
+                content_length = value.parse().map_err(|err| {
+                    invalid_data(format!("invalid content length `{value}`: {err}"))
+                })?
+            }
+            _ => {}
+        }
+    }
+
+    if content_length > MAX_BODY {
+        return Err(invalid_data(format!(
+            "content length {content_length} is over the limit of {MAX_BODY} bytes"
+        )));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    // the query string isn't part of the route
+    let path = target.split_once('?').map_or(target, |(path, _)| path);
+    let context = Context::new(path.into(), id).with_body(body);
+    Ok((path.into(), context))
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// A plain text response
+#[derive(Debug, PartialEq)]
+struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    fn new(status: u16, body: impl Into<String>) -> Self {
+        Response {
+            status,
+            body: body.into(),
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let reason = match self.status {
+            200 => "OK",
+            204 => "No Content",
+            400 => "Bad Request",
+            404 => "Not Found",
+            _ => "Internal Server Error",
+        };
+
+        // a 204 response has no body, so it doesn't get a length either
+        let content_length = match self.status {
+            204 => String::new(),
+            _ => format!("content-length: {}\r\n", self.body.len()),
+        };
+
+        format!(
+            "HTTP/1.1 {} {reason}\r\ncontent-type: text/plain; charset=utf-8\r\n{content_length}connection: close\r\n\r\n{}",
+            self.status, self.body
+        )
+        .into_bytes()
+    }
+}
+
+/// Maps an `Output` to a status code, like Axums `IntoResponse` implementations do
+impl From<Output> for Response {
+    fn from(output: Output) -> Self {
+        match output {
+            Output::Empty => Response::new(204, ""),
+            Output::Text(text) => Response::new(200, text),
+            Output::Number(number) => Response::new(200, number.to_string()),
+            Output::Error(message) => Response::new(500, message),
+            Output::Rejected(rejection) => Response::new(400, rejection.message()),
+            Output::Many(outputs) => {
+                let responses: Vec<_> = outputs.into_iter().map(Response::from).collect();
+                // the first failure wins, otherwise the bodies are joined line by line
+                match responses.iter().find(|response| response.status >= 400) {
+                    Some(failed) => Response::new(failed.status, failed.body.clone()),
+                    None => Response::new(
+                        200,
+                        responses
+                            .iter()
+                            .map(|response| response.body.as_str())
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                    ),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Rejection;
+
+    use super::*;
+
+    #[test]
+    fn reads_path_id_and_body() {
+        let mut request =
+            "POST /echo?ignored=1 HTTP/1.1\r\nHost: localhost\r\nX-Id: 7\r\nContent-Length: 5\r\n\r\nhello".as_bytes();
+
+        let (path, context) = read_request(&mut request).unwrap();
+
+        assert_eq!(path, "/echo");
+        assert_eq!(context.param(), "/echo");
+        assert_eq!(context.id(), 7);
+        assert_eq!(context.body(), b"hello");
+    }
+
+    #[test]
+    fn missing_id_defaults_to_zero() {
+        let mut request = "GET / HTTP/1.1\r\n\r\n".as_bytes();
+
+        let (_, context) = read_request(&mut request).unwrap();
+
+        assert_eq!(context.id(), 0);
+        assert_eq!(context.body(), b"");
+    }
+
+    #[test]
+    fn malformed_requests_are_invalid_data() {
+        for request in [
+            "GET\r\n\r\n",
+            "GET / SMTP\r\n\r\n",
+            "GET / HTTP/1.1\r\nno colon\r\n\r\n",
+            "GET / HTTP/1.1\r\nX-Id: seven\r\n\r\n",
+            "GET / HTTP/1.1\r\n",
+            "POST / HTTP/1.1\r\nContent-Length: 99999999999\r\n\r\n",
+        ] {
+            let Err(err) = read_request(&mut request.as_bytes()) else {
+                panic!("{request:?} should be rejected");
+            };
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{request:?}");
+        }
+    }
+
+    #[test]
+    fn outputs_map_to_status_codes() {
+        assert_eq!(Response::from(Output::Empty), Response::new(204, ""));
+        assert_eq!(Response::from(Output::Number(3)), Response::new(200, "3"));
+        assert_eq!(
+            Response::from(Output::Error("boom".into())),
+            Response::new(500, "boom")
+        );
+        assert_eq!(
+            Response::from(Output::Rejected(Rejection::new("nope"))),
+            Response::new(400, "nope")
+        );
+        assert_eq!(
+            Response::from(Output::Many(vec![
+                Output::Text("a".into()),
+                Output::Number(1)
+            ])),
+            Response::new(200, "a\n1")
+        );
+        assert_eq!(
+            Response::from(Output::Many(vec![
+                Output::Text("a".into()),
+                Output::Error("boom".into())
+            ])),
+            Response::new(500, "boom")
+        );
+    }
+
+    #[test]
+    fn response_has_status_line_and_length() {
+        let bytes = Response::new(404, "no route for /x").to_bytes();
+
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "HTTP/1.1 404 Not Found\r\ncontent-type: text/plain; charset=utf-8\r\ncontent-length: 15\r\nconnection: close\r\n\r\nno route for /x"
+        );
+    }
+
+    #[test]
+    fn no_content_response_has_no_length() {
+        let bytes = Response::new(204, "").to_bytes();
+
+        assert_eq!(
+            String::from_utf8(bytes).unwrap(),
+            "HTTP/1.1 204 No Content\r\ncontent-type: text/plain; charset=utf-8\r\nconnection: close\r\n\r\n"
+        );
+    }
+}
@@ -0,0 +1,45 @@
+// lets `#[derive(FromContext)]` refer to `::rust_magic_fn` from inside this crate too
+extern crate self as rust_magic_fn;
+
+#[macro_use]
+mod macros;
+
+mod borrow;
+mod cache;
+mod controller;
+mod describe;
+mod events;
+mod executor;
+mod extensions;
+mod http;
+mod magic;
+mod middleware;
+mod output;
+mod path;
+mod query;
+mod rejection;
+mod router;
+mod service;
+mod state;
+mod systems;
+
+pub use borrow::*;
+pub use cache::*;
+pub use controller::*;
+pub use describe::*;
+pub use events::*;
+pub use executor::*;
+pub use extensions::*;
+pub use http::*;
+pub use magic::*;
+pub use middleware::*;
+pub use output::*;
+pub use path::*;
+pub use query::*;
+pub use rejection::*;
+pub use router::*;
+pub use service::*;
+pub use state::*;
+pub use systems::*;
+
+pub use rust_magic_fn_macros::{debug_handler, FromContext, FromQuery};
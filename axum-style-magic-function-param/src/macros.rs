@@ -0,0 +1,32 @@
+/// Calls the given macro once for each arity from 0 to 16.
+/// The last type is passed on its own, e.g. `$name!([T1, T2], T3)`, so implementations can treat it differently
+macro_rules! all_the_tuples {
+    ($name:ident) => {
+        $name!([]);
+        $name!([], T1);
+        $name!([T1], T2);
+        $name!([T1, T2], T3);
+        $name!([T1, T2, T3], T4);
+        $name!([T1, T2, T3, T4], T5);
+        $name!([T1, T2, T3, T4, T5], T6);
+        $name!([T1, T2, T3, T4, T5, T6], T7);
+        $name!([T1, T2, T3, T4, T5, T6, T7], T8);
+        $name!([T1, T2, T3, T4, T5, T6, T7, T8], T9);
+        $name!([T1, T2, T3, T4, T5, T6, T7, T8, T9], T10);
+        $name!([T1, T2, T3, T4, T5, T6, T7, T8, T9, T10], T11);
+        $name!([T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11], T12);
+        $name!([T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12], T13);
+        $name!(
+            [T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13],
+            T14
+        );
+        $name!(
+            [T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14],
+            T15
+        );
+        $name!(
+            [T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15],
+            T16
+        );
+    };
+}
@@ -1,62 +1,574 @@
+use std::future::Future;
+
+use crate::{
+    cache::ExtractorCache,
+    output::{IntoOutput, Output},
+    Extensions, ExtractorDescription, HandlerMutService, HandlerService, Layer, Rejection,
+};
+
+/// The data handlers extract their arguments from.
+/// `S` is the application state shared by all handlers, see `State`
 #[derive(Clone)]
-pub struct Context {
+pub struct Context<S = ()> {
     param: String,
     id: u32,
+    body: Vec<u8>,
+    extensions: Extensions,
+    cache: ExtractorCache,
+    state: S,
 }
 
 impl Context {
     pub fn new(param: String, id: u32) -> Self {
-        Context { param, id }
+        Context {
+            param,
+            id,
+            body: Vec::new(),
+            extensions: Extensions::new(),
+            cache: ExtractorCache::default(),
+            state: (),
+        }
+    }
+}
+
+impl<S> Context<S> {
+    /// Replaces the context application state.
+    /// Cached extractor values may have been extracted from the old state, so they are dropped
+    pub fn with_state<S2>(self, state: S2) -> Context<S2> {
+        Context {
+            param: self.param,
+            id: self.id,
+            body: self.body,
+            extensions: self.extensions,
+            cache: ExtractorCache::default(),
+            state,
+        }
+    }
+
+    pub fn with_body(self, body: impl Into<Vec<u8>>) -> Self {
+        Context {
+            body: body.into(),
+            ..self
+        }
+    }
+
+    pub fn param(&self) -> &str {
+        &self.param
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    pub(crate) fn cache(&self) -> &ExtractorCache {
+        &self.cache
     }
 }
+
 pub struct Param(pub String);
 
 pub struct Id(pub u32);
 
-pub trait FromContext {
-    fn from_context(context: &Context) -> Self;
+/// Takes the context body, only possible as the last handler argument, see `FromContextOnce`
+pub struct Body(pub Vec<u8>);
+
+/// Extracts a handler argument from the context, or rejects the context when it can't
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not an extractor",
+    label = "not an extractor",
+    note = "extractors implement `FromContext`, e.g. `Param`, `Id` or `State<T>`"
+)]
+pub trait FromContext<S = ()>: Sized {
+    fn from_context(context: &Context<S>) -> Result<Self, Rejection>;
+
+    /// Describes the extractor for generated docs, override it to tell where the value comes from
+    fn describe() -> ExtractorDescription {
+        ExtractorDescription::of::<Self>("context")
+    }
 }
 
-impl FromContext for Param {
-    fn from_context(context: &Context) -> Self {
-        Param(context.param.clone())
+impl<S> FromContext<S> for Param {
+    fn from_context(context: &Context<S>) -> Result<Self, Rejection> {
+        Ok(Param(context.param.clone()))
+    }
+
+    fn describe() -> ExtractorDescription {
+        ExtractorDescription::of::<Self>("param")
     }
 }
 
-impl FromContext for Id {
-    fn from_context(context: &Context) -> Self {
-        Id(context.id)
+impl<S> FromContext<S> for Id {
+    fn from_context(context: &Context<S>) -> Result<Self, Rejection> {
+        Ok(Id(context.id))
+    }
+
+    fn describe() -> ExtractorDescription {
+        ExtractorDescription::of::<Self>("id")
     }
 }
 
-pub trait Handler<T> {
-    fn call(self, context: Context);
+/// Async version of `FromContext`, for extractors that need to await something,
+/// e.g. loading a user from a database.
+/// Every `FromContext` extractor gets this implementation for free
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can't be extracted before the last handler argument",
+    label = "not an extractor that can come before the last argument",
+    note = "only extractors implementing `FromContext` or `AsyncFromContext` can come before the last argument",
+    note = "extractors consuming the context, like `Body`, must be the last argument"
+)]
+pub trait AsyncFromContext<S = ()>: Sized {
+    fn from_context_async(context: &Context<S>) -> impl Future<Output = Result<Self, Rejection>>;
+
+    fn describe() -> ExtractorDescription {
+        ExtractorDescription::of::<Self>("context")
+    }
 }
 
-impl<F, T> Handler<T> for F
+impl<S, T> AsyncFromContext<S> for T
 where
-    F: Fn(T),
-    T: FromContext,
+    T: FromContext<S>,
 {
-    fn call(self, context: Context) {
-        (self)(T::from_context(&context));
+    async fn from_context_async(context: &Context<S>) -> Result<Self, Rejection> {
+        T::from_context(context)
+    }
+
+    fn describe() -> ExtractorDescription {
+        T::describe()
+    }
+}
+
+mod private {
+    // markers telling apart the `FromContextOnce` blanket implementation from the consuming ones,
+    // otherwise the compiler can't rule out someone implementing both for the same type
+
+    #[derive(Debug, Clone, Copy)]
+    pub enum ViaContext {}
+
+    #[derive(Debug, Clone, Copy)]
+    pub enum ViaContextOnce {}
+}
+
+/// An extractor that consumes the context, Axums `FromRequest`.
+/// Since the context can only be consumed once, a `FromContextOnce` extractor is only
+/// allowed as the last handler argument. Every `AsyncFromContext` extractor gets this implementation for free.
+/// `M` is an implementation detail, leave it as the default when implementing
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not an extractor",
+    label = "not an extractor",
+    note = "extractors implement `FromContext`, `AsyncFromContext` or `FromContextOnce`"
+)]
+pub trait FromContextOnce<S = (), M = private::ViaContextOnce>: Sized {
+    fn from_context_once(context: Context<S>) -> impl Future<Output = Result<Self, Rejection>>;
+
+    fn describe() -> ExtractorDescription {
+        ExtractorDescription::of::<Self>("context")
     }
 }
 
-impl<T1, T2, F> Handler<(T1, T2)> for F
+impl<S, T> FromContextOnce<S, private::ViaContext> for T
 where
-    F: Fn(T1, T2),
-    T1: FromContext,
-    T2: FromContext,
+    T: AsyncFromContext<S>,
 {
-    fn call(self, context: Context) {
-        (self)(T1::from_context(&context), T2::from_context(&context));
+    async fn from_context_once(context: Context<S>) -> Result<Self, Rejection> {
+        T::from_context_async(&context).await
+    }
+
+    fn describe() -> ExtractorDescription {
+        <T as AsyncFromContext<S>>::describe()
+    }
+}
+
+impl<S> FromContextOnce<S> for Body {
+    async fn from_context_once(context: Context<S>) -> Result<Self, Rejection> {
+        // moves the body out instead of cloning it, this is why it has to be last
+        Ok(Body(context.body))
+    }
+
+    fn describe() -> ExtractorDescription {
+        ExtractorDescription::of::<Self>("body")
     }
 }
 
-pub fn trigger<T, H>(context: Context, handler: H)
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` is not a handler",
+    label = "not a handler",
+    note = "handlers are async functions taking up to 16 extractors and returning a type implementing `IntoOutput`",
+    note = "extractors consuming the context, like `Body`, must be the last argument, so a handler can take only one of them"
+)]
+pub trait Handler<T, S = ()> {
+    /// Calls the handler without consuming it, so it can be called again and keep state between calls,
+    /// e.g. a closure counting its calls
+    fn call_mut(&mut self, context: Context<S>) -> impl Future<Output = Output>;
+
+    fn call(mut self, context: Context<S>) -> impl Future<Output = Output>
+    where
+        Self: Sized,
+    {
+        async move { self.call_mut(context).await }
+    }
+
+    /// Describes the handler arguments, in order
+    fn describe() -> Vec<ExtractorDescription>;
+
+    fn into_service(self) -> HandlerService<Self, T, S>
+    where
+        Self: Sized,
+    {
+        HandlerService::new(self)
+    }
+
+    /// Turns the handler into a service that calls this one instance every time, rather than a clone of it.
+    /// Handlers don't need to be `Clone` for it, and state they mutate is kept between calls
+    fn into_service_mut(self) -> HandlerMutService<Self, T, S>
+    where
+        Self: Sized,
+    {
+        HandlerMutService::new(self)
+    }
+
+    /// Wraps the handler with a middleware layer, e.g. `handler.layer(TimingLayer::new(..))`
+    fn layer<L>(self, layer: L) -> L::Service
+    where
+        Self: Sized,
+        L: Layer<HandlerService<Self, T, S>>,
+    {
+        layer.layer(self.into_service())
+    }
+}
+
+/// Implements `Handler` for closures of every arity passed in, e.g. `impl_handler!([T1], T2)`
+/// generates the implementation for `FnMut(T1, T2) -> impl Future`.
+/// The tuple `(M, T1, T2)` is only used to tell the implementations apart, it is never constructed.
+/// Only the last argument may consume the context, `M` tells how it is extracted.
+/// When an extractor rejects, the handler is not called and the rejection is the output
+macro_rules! impl_handler {
+    ([]) => {
+        impl<F, Fut, S> Handler<(), S> for F
+        where
+            F: FnMut() -> Fut,
+            Fut: Future,
+            Fut::Output: IntoOutput,
+        {
+            async fn call_mut(&mut self, _context: Context<S>) -> Output {
+                (self)().await.into_output()
+            }
+
+            fn describe() -> Vec<ExtractorDescription> {
+                Vec::new()
+            }
+        }
+    };
+    ([$($ty:ident),*], $last:ident) => {
+        #[allow(non_snake_case)]
+        impl<F, Fut, S, M, $($ty,)* $last> Handler<(M, $($ty,)* $last,), S> for F
+        where
+            F: FnMut($($ty,)* $last) -> Fut,
+            Fut: Future,
+            Fut::Output: IntoOutput,
+            $($ty: AsyncFromContext<S>,)*
+            $last: FromContextOnce<S, M>,
+        {
+            async fn call_mut(&mut self, context: Context<S>) -> Output {
+                $(
+                    let $ty = match $ty::from_context_async(&context).await {
+                        Ok(value) => value,
+                        Err(rejection) => return rejection.into_output(),
+                    };
+                )*
+                let $last = match $last::from_context_once(context).await {
+                    Ok(value) => value,
+                    Err(rejection) => return rejection.into_output(),
+                };
+                (self)($($ty,)* $last).await.into_output()
+            }
+
+            fn describe() -> Vec<ExtractorDescription> {
+                vec![
+                    $(<$ty as AsyncFromContext<S>>::describe(),)*
+                    <$last as FromContextOnce<S, M>>::describe(),
+                ]
+            }
+        }
+    };
+}
+
+all_the_tuples!(impl_handler);
+
+pub async fn trigger<T, S, H>(context: Context<S>, handler: H) -> Output
 where
-    H: Handler<T>,
+    H: Handler<T, S>,
 {
-    handler.call(context);
+    handler.call(context).await
+}
+
+/// Calls a handler without consuming it, so the same handler can be triggered again
+pub async fn trigger_mut<T, S, H>(context: Context<S>, handler: &mut H) -> Output
+where
+    H: Handler<T, S>,
+{
+    handler.call_mut(context).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use crate::{executor::block_on, FromContext, State};
+
+    use super::*;
+
+    /// What the extractors `assert_triggers` uses turn into, with the context it triggers with
+    trait Extracted {
+        const EXPECTED: &'static str;
+
+        fn text(self) -> String;
+    }
+
+    impl Extracted for Param {
+        const EXPECTED: &'static str = "magic";
+
+        fn text(self) -> String {
+            self.0
+        }
+    }
+
+    impl Extracted for Id {
+        const EXPECTED: &'static str = "33";
+
+        fn text(self) -> String {
+            self.0.to_string()
+        }
+    }
+
+    /// Triggers a handler taking the given extractors and returning their values,
+    /// and asserts that the output has one value per argument, in order
+    macro_rules! assert_triggers {
+        ($($arg:ident: $ty:ident),*) => {{
+            let output = block_on(trigger(
+                Context::new("magic".into(), 33),
+                |$($arg: $ty),*| async move { ($($arg.text(),)*) },
+            ));
+            assert_eq!(output, Output::Many(vec![$(Output::Text($ty::EXPECTED.into())),*]));
+        }};
+    }
+
+    /// An extractor that has to await before it is ready
+    struct SlowId(u32);
+
+    impl AsyncFromContext for SlowId {
+        async fn from_context_async(context: &Context) -> Result<Self, Rejection> {
+            let id = crate::executor::tests::YieldOnce(false).await;
+            Ok(SlowId(context.id + id))
+        }
+    }
+
+    #[test]
+    fn trigger_calls_handler_without_params() {
+        let called = &RefCell::new(false);
+        block_on(trigger(
+            Context::new("magic".into(), 33),
+            move || async move { *called.borrow_mut() = true },
+        ));
+        assert!(called.take());
+    }
+
+    #[test]
+    fn trigger_passes_extracted_values() {
+        let received = &RefCell::new(None);
+        block_on(trigger(
+            Context::new("magic".into(), 33),
+            move |Id(id): Id, Param(param): Param, Id(id2): Id| async move {
+                *received.borrow_mut() = Some((id, param, id2));
+            },
+        ));
+        assert_eq!(received.take(), Some((33, "magic".to_string(), 33)));
+    }
+
+    #[test]
+    fn trigger_awaits_async_extractors() {
+        let received = &RefCell::new(None);
+        block_on(trigger(
+            Context::new("magic".into(), 33),
+            move |SlowId(id): SlowId, Param(param): Param| async move {
+                *received.borrow_mut() = Some((id, param));
+            },
+        ));
+        assert_eq!(received.take(), Some((75, "magic".to_string())));
+    }
+
+    #[test]
+    fn trigger_supports_async_fn() {
+        async fn handler(Id(id): Id) {
+            assert_eq!(id, 33);
+        }
+
+        block_on(trigger(Context::new("magic".into(), 33), handler));
+    }
+
+    #[test]
+    fn trigger_returns_handler_output() {
+        async fn handler(Param(param): Param, Id(id): Id) -> Result<String, String> {
+            Ok(format!("{param}-{id}"))
+        }
+
+        let output = block_on(trigger(Context::new("magic".into(), 33), handler));
+        assert_eq!(output, Output::Text("magic-33".into()));
+    }
+
+    #[test]
+    fn trigger_mut_keeps_handler_state() {
+        let mut count = 0;
+        let mut handler = move |Id(id): Id| {
+            count += id;
+            std::future::ready(count)
+        };
+
+        let outputs: Vec<_> = (1..=3)
+            .map(|id| block_on(trigger_mut(Context::new("magic".into(), id), &mut handler)))
+            .collect();
+
+        assert_eq!(
+            outputs,
+            [Output::Number(1), Output::Number(3), Output::Number(6)]
+        );
+    }
+
+    #[test]
+    fn cloned_handlers_start_from_the_same_state() {
+        // a `Vec` rather than a counter, so the closure is `Clone` but not `Copy`
+        let mut calls = Vec::new();
+        let handler = move || {
+            calls.push(());
+            std::future::ready(calls.len() as u32)
+        };
+
+        let first = block_on(trigger(Context::new("magic".into(), 33), handler.clone()));
+        let second = block_on(trigger(Context::new("magic".into(), 33), handler.clone()));
+
+        assert_eq!(first, Output::Number(1));
+        assert_eq!(second, Output::Number(1));
+    }
+
+    #[test]
+    fn body_is_extracted_by_last_argument() {
+        let output = block_on(trigger(
+            Context::new("magic".into(), 33).with_body("payload"),
+            |Id(id): Id, Body(body): Body| async move { format!("{id} {}", body.len()) },
+        ));
+        assert_eq!(output, Output::Text("33 7".into()));
+    }
+
+    #[test]
+    fn extractors_still_work_as_last_argument() {
+        let output = block_on(trigger(
+            Context::new("magic".into(), 33).with_state(7u32),
+            |State(state): State<u32>, Id(id): Id| async move { format!("{state} {id}") },
+        ));
+        assert_eq!(output, Output::Text("7 33".into()));
+
+        let output = block_on(trigger(
+            Context::new("magic".into(), 33),
+            |Id(id): Id, Param(param): Param| async move { format!("{id} {param}") },
+        ));
+        assert_eq!(output, Output::Text("33 magic".into()));
+    }
+
+    #[crate::debug_handler]
+    async fn checked_handler(Param(param): Param, Id(id): Id, Body(body): Body) -> String {
+        format!("{param} {id} {}", body.len())
+    }
+
+    #[test]
+    fn debug_handler_keeps_handler_working() {
+        let output = block_on(trigger(
+            Context::new("magic".into(), 33).with_body("abc"),
+            checked_handler,
+        ));
+        assert_eq!(output, Output::Text("magic 33 3".into()));
+    }
+
+    #[derive(FromContext)]
+    struct Request {
+        param: Param,
+        id: Id,
+    }
+
+    #[derive(FromContext)]
+    struct IdPair(Id, Id);
+
+    #[test]
+    fn derived_extractor_extracts_every_field() {
+        let output = block_on(trigger(
+            Context::new("magic".into(), 33),
+            |Request { param, id }: Request, IdPair(first, second): IdPair| async move {
+                format!("{} {} {} {}", param.0, id.0, first.0, second.0)
+            },
+        ));
+        assert_eq!(output, Output::Text("magic 33 33 33".into()));
+    }
+
+    fn describe<T, H>(_handler: &H) -> Vec<ExtractorDescription>
+    where
+        H: Handler<T>,
+    {
+        H::describe()
+    }
+
+    #[test]
+    fn handler_describes_extractors_in_order() {
+        let sources = |descriptions: Vec<ExtractorDescription>| {
+            descriptions
+                .into_iter()
+                .map(|description| (description.name, description.source))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(sources(describe(&|| async {})), []);
+        assert_eq!(
+            sources(describe(&|_: Id, _: SlowId, _: Param, _: Body| async {})),
+            [
+                ("Id", "id"),
+                ("SlowId", "context"),
+                ("Param", "param"),
+                ("Body", "body"),
+            ]
+        );
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn trigger_supports_every_arity() {
+        assert_triggers!(a: Param);
+        assert_triggers!(a: Id, b: Param);
+        assert_triggers!(a: Param, b: Id, c: Param);
+        assert_triggers!(a: Id, b: Id, c: Param, d: Id);
+        assert_triggers!(a: Param, b: Id, c: Param, d: Id, e: Param);
+        assert_triggers!(a: Id, b: Param, c: Id, d: Param, e: Id, f: Param);
+        assert_triggers!(a: Param, b: Param, c: Id, d: Id, e: Param, f: Param, g: Id);
+        assert_triggers!(a: Id, b: Param, c: Id, d: Param, e: Id, f: Param, g: Id, h: Param);
+        assert_triggers!(a: Param, b: Id, c: Param, d: Id, e: Param, f: Id, g: Param, h: Id, i: Param);
+        assert_triggers!(a: Id, b: Param, c: Id, d: Param, e: Id, f: Param, g: Id, h: Param, i: Id, j: Param);
+        assert_triggers!(a: Param, b: Id, c: Param, d: Id, e: Param, f: Id, g: Param, h: Id, i: Param, j: Id, k: Param);
+        assert_triggers!(a: Id, b: Param, c: Id, d: Param, e: Id, f: Param, g: Id, h: Param, i: Id, j: Param, k: Id, l: Param);
+        assert_triggers!(a: Param, b: Id, c: Param, d: Id, e: Param, f: Id, g: Param, h: Id, i: Param, j: Id, k: Param, l: Id, m: Param);
+        assert_triggers!(a: Id, b: Param, c: Id, d: Param, e: Id, f: Param, g: Id, h: Param, i: Id, j: Param, k: Id, l: Param, m: Id, n: Param);
+        assert_triggers!(a: Param, b: Id, c: Param, d: Id, e: Param, f: Id, g: Param, h: Id, i: Param, j: Id, k: Param, l: Id, m: Param, n: Id, o: Param);
+        assert_triggers!(a: Id, b: Param, c: Id, d: Param, e: Id, f: Param, g: Id, h: Param, i: Id, j: Param, k: Id, l: Param, m: Id, n: Param, o: Id, p: Param);
+    }
 }
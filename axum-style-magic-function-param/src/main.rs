@@ -1,28 +1,84 @@
-use magic::{trigger, Context, Id, Param};
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Arc,
+};
 
-mod magic;
+use rust_magic_fn::{
+    block_on, trigger, AllowIdsLayer, Body, Context, Handler, Id, Param, Router, State, TimingLayer,
+};
 
-fn print_id(id: Id) {
+async fn print_id(id: Id) {
     println!("id is {}", id.0);
 }
 
-fn print_param(Param(param): Param) {
+async fn print_param(Param(param): Param) {
     println!("param is {param}");
 }
 
-fn print_all(Param(param): Param, Id(id): Id) {
+async fn print_all(Param(param): Param, Id(id): Id) {
     println!("param is {param}, id is {id}");
 }
 
-fn print_all_switched(Id(id): Id, Param(param): Param) {
+async fn print_hello() {
+    println!("hello, no params here");
+}
+
+async fn print_twice(Param(param): Param, Id(id): Id, Param(again): Param) {
+    println!("param is {param}, id is {id}, param again is {again}");
+}
+
+async fn print_all_switched(Id(id): Id, Param(param): Param) {
     println!("param is {param}, id is {id}");
 }
 
+async fn greet(Param(param): Param, Id(id): Id) -> String {
+    format!("hello {param} number {id}")
+}
+
+async fn print_body(Id(id): Id, Body(body): Body) {
+    println!("id is {id}, body is {}", String::from_utf8_lossy(&body));
+}
+
+async fn count_visit(State(visits): State<Arc<AtomicU32>>) -> u32 {
+    visits.fetch_add(1, Ordering::SeqCst) + 1
+}
+
 pub fn main() {
+    block_on(run());
+}
+
+async fn run() {
     let context = Context::new("magic".into(), 33);
 
-    trigger(context.clone(), print_id);
-    trigger(context.clone(), print_param);
-    trigger(context.clone(), print_all);
-    trigger(context, print_all_switched);
+    trigger(context.clone(), print_id).await;
+    trigger(context.clone(), print_param).await;
+    trigger(context.clone(), print_all).await;
+    trigger(context.clone(), print_all_switched).await;
+    trigger(context.clone(), print_hello).await;
+    trigger(context.clone(), print_twice).await;
+
+    trigger(context.clone().with_body("some payload"), print_body).await;
+
+    let output = trigger(context.clone(), greet).await;
+    println!("greet returned {output:?}");
+
+    let router = Router::new()
+        .route("/id", print_id)
+        .route("/all", print_all)
+        .route("/greet", greet)
+        .route_service("/secret", greet.layer(AllowIdsLayer::new([1])))
+        .layer(TimingLayer::new(|elapsed| {
+            println!("handled in {elapsed:?}")
+        }));
+
+    for path in ["/id", "/all", "/greet", "/secret", "/unknown"] {
+        let output = router.dispatch(path, context.clone()).await;
+        println!("{path} returned {output:?}");
+    }
+
+    let visits = Arc::new(AtomicU32::new(0));
+    let context = context.with_state(visits);
+    trigger(context.clone(), count_visit).await;
+    let output = trigger(context, count_visit).await;
+    println!("visits {output:?}");
 }
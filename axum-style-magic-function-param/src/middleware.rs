@@ -0,0 +1,442 @@
+use std::{
+    any::Any,
+    collections::HashSet,
+    future::Future,
+    panic::{catch_unwind, AssertUnwindSafe},
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+    time::{Duration, Instant},
+};
+
+use crate::{Context, ExtractorDescription, Output, Service};
+
+/// Wraps a service with another service, Towers `Layer`.
+/// This is how middleware is written - the wrapping service can act before and after calling the inner one
+pub trait Layer<Svc> {
+    type Service;
+
+    fn layer(&self, inner: Svc) -> Self::Service;
+}
+
+/// Measures how long the inner service takes, reporting it to `on_elapsed`
+#[derive(Clone)]
+pub struct TimingLayer<F> {
+    on_elapsed: F,
+}
+
+impl<F> TimingLayer<F>
+where
+    F: Fn(Duration) + Clone,
+{
+    pub fn new(on_elapsed: F) -> Self {
+        TimingLayer { on_elapsed }
+    }
+}
+
+impl<Svc, F> Layer<Svc> for TimingLayer<F>
+where
+    F: Clone,
+{
+    type Service = Timing<Svc, F>;
+
+    fn layer(&self, inner: Svc) -> Self::Service {
+        Timing {
+            inner,
+            on_elapsed: self.on_elapsed.clone(),
+        }
+    }
+}
+
+pub struct Timing<Svc, F> {
+    inner: Svc,
+    on_elapsed: F,
+}
+
+impl<Svc, F> Service for Timing<Svc, F>
+where
+    Svc: Service,
+    F: Fn(Duration),
+{
+    type State = Svc::State;
+
+    async fn call(&self, context: Context<Self::State>) -> Output {
+        let start = Instant::now();
+        let output = self.inner.call(context).await;
+        (self.on_elapsed)(start.elapsed());
+
+        output
+    }
+
+    fn describe(&self) -> Option<Vec<ExtractorDescription>> {
+        self.inner.describe()
+    }
+}
+
+/// Only lets contexts with an allowed id through, anything else gets an error output
+/// without calling the inner service
+#[derive(Clone)]
+pub struct AllowIdsLayer {
+    ids: HashSet<u32>,
+}
+
+impl AllowIdsLayer {
+    pub fn new(ids: impl IntoIterator<Item = u32>) -> Self {
+        AllowIdsLayer {
+            ids: ids.into_iter().collect(),
+        }
+    }
+}
+
+impl<Svc> Layer<Svc> for AllowIdsLayer {
+    type Service = AllowIds<Svc>;
+
+    fn layer(&self, inner: Svc) -> Self::Service {
+        AllowIds {
+            inner,
+            ids: self.ids.clone(),
+        }
+    }
+}
+
+pub struct AllowIds<Svc> {
+    inner: Svc,
+    ids: HashSet<u32>,
+}
+
+impl<Svc> Service for AllowIds<Svc>
+where
+    Svc: Service,
+{
+    type State = Svc::State;
+
+    async fn call(&self, context: Context<Self::State>) -> Output {
+        if !self.ids.contains(&context.id()) {
+            return Output::Error(format!("id {} is not allowed", context.id()));
+        }
+
+        self.inner.call(context).await
+    }
+
+    fn describe(&self) -> Option<Vec<ExtractorDescription>> {
+        self.inner.describe()
+    }
+}
+
+/// Turns a panic in the inner service, extractors included, into an error output
+/// instead of unwinding into the caller
+#[derive(Clone, Default)]
+pub struct CatchPanicLayer;
+
+impl CatchPanicLayer {
+    pub fn new() -> Self {
+        CatchPanicLayer
+    }
+}
+
+impl<Svc> Layer<Svc> for CatchPanicLayer {
+    type Service = CatchPanic<Svc>;
+
+    fn layer(&self, inner: Svc) -> Self::Service {
+        CatchPanic { inner }
+    }
+}
+
+pub struct CatchPanic<Svc> {
+    inner: Svc,
+}
+
+impl<Svc> Service for CatchPanic<Svc>
+where
+    Svc: Service,
+{
+    type State = Svc::State;
+
+    async fn call(&self, context: Context<Self::State>) -> Output {
+        // the call itself may panic before returning a future, e.g. while cloning the handler
+        let future = match catch_unwind(AssertUnwindSafe(|| self.inner.call(context))) {
+            Ok(future) => future,
+            Err(panic) => return panic_output(panic),
+        };
+
+        CatchUnwind(Box::pin(future))
+            .await
+            .unwrap_or_else(panic_output)
+    }
+
+    fn describe(&self) -> Option<Vec<ExtractorDescription>> {
+        self.inner.describe()
+    }
+}
+
+/// Polls the inner future inside `catch_unwind`, resolving to the panic payload if it panics.
+/// The future is boxed so it can be polled without unsafe pin projections
+struct CatchUnwind<Fut>(Pin<Box<Fut>>);
+
+impl<Fut> Future for CatchUnwind<Fut>
+where
+    Fut: Future,
+{
+    type Output = Result<Fut::Output, Box<dyn Any + Send>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        // the panicked future is never polled again, so a broken invariant inside it can't be observed
+        match catch_unwind(AssertUnwindSafe(|| self.0.as_mut().poll(cx))) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(panic) => Poll::Ready(Err(panic)),
+        }
+    }
+}
+
+fn panic_output(panic: Box<dyn Any + Send>) -> Output {
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic");
+
+    Output::Error(format!("handler panicked: {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{
+        block_on, executor::tests::YieldOnce, trigger, FromContext, Handler, Id, Rejection, Router,
+        ServiceExt,
+    };
+
+    use super::*;
+
+    type Log = Rc<RefCell<Vec<String>>>;
+
+    /// Logs its name before and after calling the inner service
+    struct RecordLayer {
+        name: &'static str,
+        log: Log,
+    }
+
+    struct Record<Svc> {
+        inner: Svc,
+        name: &'static str,
+        log: Log,
+    }
+
+    impl<Svc> Layer<Svc> for RecordLayer {
+        type Service = Record<Svc>;
+
+        fn layer(&self, inner: Svc) -> Self::Service {
+            Record {
+                inner,
+                name: self.name,
+                log: self.log.clone(),
+            }
+        }
+    }
+
+    impl<Svc> Service for Record<Svc>
+    where
+        Svc: Service,
+    {
+        type State = Svc::State;
+
+        async fn call(&self, context: Context<Self::State>) -> Output {
+            self.log.borrow_mut().push(format!("{} before", self.name));
+            let output = self.inner.call(context).await;
+            self.log.borrow_mut().push(format!("{} after", self.name));
+
+            output
+        }
+    }
+
+    fn record(name: &'static str, log: &Log) -> RecordLayer {
+        RecordLayer {
+            name,
+            log: log.clone(),
+        }
+    }
+
+    fn logging_handler(log: &Log) -> impl Fn(Id) -> std::future::Ready<u32> + Clone {
+        let log = log.clone();
+        move |Id(id): Id| {
+            log.borrow_mut().push("handler".into());
+            std::future::ready(id)
+        }
+    }
+
+    #[test]
+    fn nested_layers_run_outermost_first() {
+        let log = Log::default();
+        let service = logging_handler(&log)
+            .layer(record("inner", &log))
+            .layer(record("outer", &log));
+
+        let output = block_on(service.call(Context::new("magic".into(), 33)));
+
+        assert_eq!(output, Output::Number(33));
+        assert_eq!(
+            log.take(),
+            [
+                "outer before",
+                "inner before",
+                "handler",
+                "inner after",
+                "outer after"
+            ]
+        );
+    }
+
+    #[test]
+    fn router_layer_wraps_route_layers() {
+        let log = Log::default();
+        let router = Router::new()
+            .route_service("/id", logging_handler(&log).layer(record("route", &log)))
+            .layer(record("router", &log));
+
+        block_on(router.dispatch("/id", Context::new("magic".into(), 33)));
+
+        assert_eq!(
+            log.take(),
+            [
+                "router before",
+                "route before",
+                "handler",
+                "route after",
+                "router after"
+            ]
+        );
+    }
+
+    #[test]
+    fn timing_layer_reports_elapsed_time() {
+        let elapsed = Rc::new(RefCell::new(None));
+        let reported = elapsed.clone();
+        let service = (|| async { std::thread::sleep(Duration::from_millis(10)) })
+            .layer(TimingLayer::new(move |d| *reported.borrow_mut() = Some(d)));
+
+        block_on(service.call(Context::new("magic".into(), 33)));
+
+        assert!(elapsed.take().unwrap() >= Duration::from_millis(10));
+    }
+
+    #[test]
+    fn allow_ids_layer_calls_inner_service_for_allowed_id() {
+        let service = (|Id(id): Id| async move { id }).layer(AllowIdsLayer::new([1, 33]));
+        let output = block_on(service.call(Context::new("magic".into(), 33)));
+        assert_eq!(output, Output::Number(33));
+    }
+
+    #[test]
+    fn allow_ids_layer_rejects_other_ids() {
+        let log = Log::default();
+        let service = logging_handler(&log).layer(AllowIdsLayer::new([1, 2]));
+
+        let output = block_on(service.call(Context::new("magic".into(), 33)));
+
+        assert_eq!(output, Output::Error("id 33 is not allowed".into()));
+        assert!(log.take().is_empty());
+    }
+
+    #[test]
+    fn router_layer_applies_to_every_route() {
+        let router = Router::new()
+            .route("/a", || async { "a".to_string() })
+            .route("/b", || async { "b".to_string() })
+            .layer(AllowIdsLayer::new([1]));
+
+        let a = block_on(router.dispatch("/a", Context::new("magic".into(), 2)));
+        let b = block_on(router.dispatch("/b", Context::new("magic".into(), 1)));
+
+        assert_eq!(a, Some(Output::Error("id 2 is not allowed".into())));
+        assert_eq!(b, Some(Output::Text("b".into())));
+    }
+
+    struct Panicking;
+
+    impl FromContext for Panicking {
+        fn from_context(_context: &Context) -> Result<Self, Rejection> {
+            panic!("extractor failed")
+        }
+    }
+
+    #[test]
+    fn catch_panic_layer_turns_panics_into_errors() {
+        let service = (|Id(id): Id| async move {
+            if id == 33 {
+                panic!("id {id} is cursed");
+            }
+            id
+        })
+        .layer(CatchPanicLayer::new());
+
+        let cursed = block_on(service.call(Context::new("magic".into(), 33)));
+        let fine = block_on(service.call(Context::new("magic".into(), 1)));
+
+        assert_eq!(
+            cursed,
+            Output::Error("handler panicked: id 33 is cursed".into())
+        );
+        assert_eq!(fine, Output::Number(1));
+    }
+
+    #[test]
+    fn catch_panic_layer_catches_panics_after_awaiting() {
+        async fn handler() -> String {
+            YieldOnce(false).await;
+            panic!("after a yield");
+        }
+
+        let service = handler.layer(CatchPanicLayer::new());
+
+        let output = block_on(service.call(Context::new("magic".into(), 33)));
+
+        assert_eq!(
+            output,
+            Output::Error("handler panicked: after a yield".into())
+        );
+    }
+
+    #[test]
+    fn catch_panic_layer_catches_extractor_panics() {
+        let service = (|_: Panicking| async {}).layer(CatchPanicLayer::new());
+
+        let output = block_on(service.call(Context::new("magic".into(), 33)));
+
+        assert_eq!(
+            output,
+            Output::Error("handler panicked: extractor failed".into())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "nobody catches this")]
+    fn panics_propagate_without_catch_panic_layer() {
+        async fn handler() -> String {
+            panic!("nobody catches this")
+        }
+
+        block_on(trigger(Context::new("magic".into(), 33), handler));
+    }
+
+    #[test]
+    fn router_keeps_dispatching_after_a_panic() {
+        async fn boom() -> String {
+            panic!("boom")
+        }
+
+        let router = Router::new()
+            .route("/panic", boom)
+            .route("/id", |Id(id): Id| async move { id })
+            .layer(CatchPanicLayer::new());
+
+        let panicked = block_on(router.dispatch("/panic", Context::new("magic".into(), 33)));
+        let id = block_on(router.dispatch("/id", Context::new("magic".into(), 33)));
+
+        assert_eq!(
+            panicked,
+            Some(Output::Error("handler panicked: boom".into()))
+        );
+        assert_eq!(id, Some(Output::Number(33)));
+    }
+}
@@ -0,0 +1,131 @@
+use std::fmt::Display;
+
+use crate::Rejection;
+
+/// The common type every handler return value is converted into, `Response` in Axums case
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    Empty,
+    Text(String),
+    Number(u32),
+    Error(String),
+    Rejected(Rejection),
+    Many(Vec<Output>),
+}
+
+/// Converts a handler return value into an `Output`, Axums `IntoResponse`.
+/// This lets handlers return whatever type is natural for them while `trigger` always returns an `Output`
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can't be returned from a handler",
+    label = "does not implement `IntoOutput`",
+    note = "handlers can return `()`, `String`, `u32`, `Output`, a `Result` or a tuple of those"
+)]
+pub trait IntoOutput {
+    fn into_output(self) -> Output;
+}
+
+impl IntoOutput for Output {
+    fn into_output(self) -> Output {
+        self
+    }
+}
+
+impl IntoOutput for String {
+    fn into_output(self) -> Output {
+        Output::Text(self)
+    }
+}
+
+impl IntoOutput for u32 {
+    fn into_output(self) -> Output {
+        Output::Number(self)
+    }
+}
+
+impl<T, E> IntoOutput for Result<T, E>
+where
+    T: IntoOutput,
+    E: Display,
+{
+    fn into_output(self) -> Output {
+        match self {
+            Ok(value) => value.into_output(),
+            Err(err) => Output::Error(err.to_string()),
+        }
+    }
+}
+
+/// `()` is the empty output, any other tuple collects the outputs of its items
+macro_rules! impl_into_output {
+    ([]) => {
+        impl IntoOutput for () {
+            fn into_output(self) -> Output {
+                Output::Empty
+            }
+        }
+    };
+    ([$($ty:ident),*], $last:ident) => {
+        #[allow(non_snake_case)]
+        impl<$($ty,)* $last> IntoOutput for ($($ty,)* $last,)
+        where
+            $($ty: IntoOutput,)*
+            $last: IntoOutput,
+        {
+            fn into_output(self) -> Output {
+                let ($($ty,)* $last,) = self;
+                Output::Many(vec![$($ty.into_output(),)* $last.into_output()])
+            }
+        }
+    };
+}
+
+all_the_tuples!(impl_into_output);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unit_into_output_is_empty() {
+        assert_eq!(().into_output(), Output::Empty);
+    }
+
+    #[test]
+    fn string_into_output_is_text() {
+        assert_eq!(
+            "magic".to_string().into_output(),
+            Output::Text("magic".into())
+        );
+    }
+
+    #[test]
+    fn u32_into_output_is_number() {
+        assert_eq!(33.into_output(), Output::Number(33));
+    }
+
+    #[test]
+    fn ok_into_output_is_inner_output() {
+        let result: Result<u32, String> = Ok(33);
+        assert_eq!(result.into_output(), Output::Number(33));
+    }
+
+    #[test]
+    fn err_into_output_is_error() {
+        let result: Result<u32, String> = Err("no magic".into());
+        assert_eq!(result.into_output(), Output::Error("no magic".into()));
+    }
+
+    #[test]
+    fn tuple_into_output_collects_items() {
+        let output = ("magic".to_string(), 33, (), Ok::<_, String>(7)).into_output();
+        assert_eq!(
+            output,
+            Output::Many(vec![
+                Output::Text("magic".into()),
+                Output::Number(33),
+                Output::Empty,
+                Output::Number(7),
+            ])
+        );
+    }
+}
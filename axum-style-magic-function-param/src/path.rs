@@ -0,0 +1,189 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{Context, ExtractorDescription, FromContext, Rejection};
+
+/// Parses the whole context param with `FromStr`, so handlers get e.g. a `u32` instead of re-parsing a string.
+/// Parse into `Segments` to split the param into a tuple. Rejects the context when parsing fails
+pub struct Path<T>(pub T);
+
+impl<S, T> FromContext<S> for Path<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    fn from_context(context: &Context<S>) -> Result<Self, Rejection> {
+        context
+            .param()
+            .parse()
+            .map(Path)
+            .map_err(|err| Rejection::new(format!("invalid path `{}`: {err}", context.param())))
+    }
+
+    fn describe() -> ExtractorDescription {
+        ExtractorDescription::of::<Self>("param")
+    }
+}
+
+/// Splits the param on `/` and parses each segment into an item of the tuple, e.g. `3/7` into `(3, 7)`.
+/// Use it as `Path<Segments<(u32, u32)>>`, since a tuple can't implement `FromStr` on its own
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segments<T>(pub T);
+
+/// The param must have exactly one segment per tuple item
+macro_rules! impl_segments {
+    ([]) => {};
+    ([$($ty:ident),*], $last:ident) => {
+        impl<$($ty,)* $last> FromStr for Segments<($($ty,)* $last,)>
+        where
+            $($ty: FromStr, $ty::Err: Display,)*
+            $last: FromStr,
+            $last::Err: Display,
+        {
+            type Err = String;
+
+            fn from_str(param: &str) -> Result<Self, Self::Err> {
+                let segments: Vec<&str> = param.trim_matches('/').split('/').collect();
+                let expected = [$(stringify!($ty),)* stringify!($last)].len();
+                if segments.len() != expected {
+                    return Err(format!(
+                        "expected {expected} segments, got {}",
+                        segments.len()
+                    ));
+                }
+
+                // the length check above guarantees there is a segment for every item
+                let mut segments = segments.into_iter();
+
+                Ok(Segments((
+                    $(parse_segment::<$ty>(segments.next().unwrap())?,)*
+                    parse_segment::<$last>(segments.next().unwrap())?,
+                )))
+            }
+        }
+    };
+}
+
+all_the_tuples!(impl_segments);
+
+fn parse_segment<T>(segment: &str) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    segment
+        .parse()
+        .map_err(|err| format!("invalid segment `{segment}`: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{block_on, trigger, Output};
+
+    use super::*;
+
+    #[test]
+    fn path_parses_numeric_param() {
+        let output = block_on(trigger(
+            Context::new("33".into(), 1),
+            |Path(id): Path<u32>| async move { id + 1 },
+        ));
+        assert_eq!(output, Output::Number(34));
+    }
+
+    #[test]
+    fn path_rejects_invalid_param() {
+        let output = block_on(trigger(
+            Context::new("magic".into(), 1),
+            |Path(id): Path<u32>| async move { id },
+        ));
+        assert_eq!(
+            output,
+            Output::Rejected(Rejection::new(
+                "invalid path `magic`: invalid digit found in string"
+            ))
+        );
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    impl FromStr for Point {
+        type Err = String;
+
+        fn from_str(param: &str) -> Result<Self, Self::Err> {
+            let (x, y) = param.split_once(',').ok_or("expected `x,y`")?;
+            let coordinate = |value: &str| {
+                value
+                    .parse()
+                    .map_err(|_| format!("invalid coordinate `{value}`"))
+            };
+            Ok(Point {
+                x: coordinate(x)?,
+                y: coordinate(y)?,
+            })
+        }
+    }
+
+    fn path<T>(param: &str) -> Result<T, Rejection>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        Path::<T>::from_context(&Context::new(param.into(), 1)).map(|Path(value)| value)
+    }
+
+    #[test]
+    fn path_parses_struct_param() {
+        assert_eq!(path::<Point>("3,-4"), Ok(Point { x: 3, y: -4 }));
+    }
+
+    #[test]
+    fn path_rejects_invalid_struct_param() {
+        assert_eq!(
+            path::<Point>("3"),
+            Err(Rejection::new("invalid path `3`: expected `x,y`"))
+        );
+    }
+
+    #[test]
+    fn path_parses_tuple_segments() {
+        assert_eq!(
+            path::<Segments<(u32, String)>>("/33/magic"),
+            Ok(Segments((33, "magic".into())))
+        );
+    }
+
+    #[test]
+    fn path_rejects_tuple_of_other_length() {
+        assert_eq!(
+            path::<Segments<(u32, u32)>>("33"),
+            Err(Rejection::new(
+                "invalid path `33`: expected 2 segments, got 1"
+            ))
+        );
+    }
+
+    #[test]
+    fn path_rejects_invalid_segment() {
+        assert_eq!(
+            path::<Segments<(u32, u32)>>("33/magic"),
+            Err(Rejection::new(
+                "invalid path `33/magic`: invalid segment `magic`: invalid digit found in string"
+            ))
+        );
+    }
+
+    #[test]
+    fn path_extractor_destructures_segments() {
+        let output = block_on(trigger(
+            Context::new("3/7".into(), 1),
+            |Path(Segments((user, post))): Path<Segments<(u32, u32)>>| async move {
+                format!("{user} {post}")
+            },
+        ));
+        assert_eq!(output, Output::Text("3 7".into()));
+    }
+}
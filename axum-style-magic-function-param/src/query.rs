@@ -0,0 +1,311 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use crate::{Context, ExtractorDescription, FromContext, Rejection};
+
+/// Parses the context param as a query string, e.g. `a=1&b=2`, into any `FromQuery` type.
+/// Derive `FromQuery` to parse into a struct. Rejects the context when parsing fails
+pub struct Query<T>(pub T);
+
+impl<S, T> FromContext<S> for Query<T>
+where
+    T: FromQuery,
+{
+    fn from_context(context: &Context<S>) -> Result<Self, Rejection> {
+        T::from_query(&QueryPairs::parse(context.param())).map(Query)
+    }
+
+    fn describe() -> ExtractorDescription {
+        ExtractorDescription::of::<Self>("param")
+    }
+}
+
+/// A type that can be built from query string pairs.
+/// Implemented for maps, lists of pairs and tuples, or derived for structs with `#[derive(FromQuery)]`
+pub trait FromQuery: Sized {
+    fn from_query(query: &QueryPairs) -> Result<Self, Rejection>;
+}
+
+/// The decoded `key=value` pairs of a query string, in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryPairs {
+    pairs: Vec<(String, String)>,
+}
+
+impl QueryPairs {
+    pub fn parse(query: &str) -> Self {
+        let pairs = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode(key), decode(value))
+            })
+            .collect();
+
+        QueryPairs { pairs }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Returns the first value of `key`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.iter().find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    /// Parses the value of a required key
+    pub fn value<T>(&self, key: &str) -> Result<T, Rejection>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.optional_value(key)?
+            .ok_or_else(|| Rejection::new(format!("missing query key `{key}`")))
+    }
+
+    /// Parses the value of a key that may be missing
+    pub fn optional_value<T>(&self, key: &str) -> Result<Option<T>, Rejection>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.get(key)
+            .map(|value| parse_value(key, value))
+            .transpose()
+    }
+}
+
+fn parse_value<T>(key: &str, value: &str) -> Result<T, Rejection>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|err| Rejection::new(format!("invalid query value for `{key}`: {err}")))
+}
+
+/// Decodes `+` into a space and `%XX` escapes into bytes, leaving malformed escapes as they are
+fn decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                // `from_str_radix` alone would also take a sign, e.g. `%+1`
+                let hex = input
+                    .get(i + 1..i + 3)
+                    .filter(|hex| hex.bytes().all(|byte| byte.is_ascii_hexdigit()));
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+impl FromQuery for QueryPairs {
+    fn from_query(query: &QueryPairs) -> Result<Self, Rejection> {
+        Ok(query.clone())
+    }
+}
+
+/// When a key repeats, the last value wins
+impl FromQuery for HashMap<String, String> {
+    fn from_query(query: &QueryPairs) -> Result<Self, Rejection> {
+        Ok(query.pairs.iter().cloned().collect())
+    }
+}
+
+impl FromQuery for Vec<(String, String)> {
+    fn from_query(query: &QueryPairs) -> Result<Self, Rejection> {
+        Ok(query.pairs.clone())
+    }
+}
+
+/// A tuple takes the query values by position, ignoring the keys, e.g. `a=1&b=2` into `(1, 2)`.
+/// The query must have exactly one value per tuple item. A tuple has no names to match, so `b=2&a=1`
+/// gives `(2, 1)`; derive `FromQuery` for a struct when the keys may come in any order
+macro_rules! impl_from_query {
+    ([]) => {};
+    ([$($ty:ident),*], $last:ident) => {
+        impl<$($ty,)* $last> FromQuery for ($($ty,)* $last,)
+        where
+            $($ty: FromStr, $ty::Err: Display,)*
+            $last: FromStr,
+            $last::Err: Display,
+        {
+            fn from_query(query: &QueryPairs) -> Result<Self, Rejection> {
+                let expected = [$(stringify!($ty),)* stringify!($last)].len();
+                if query.len() != expected {
+                    return Err(Rejection::new(format!(
+                        "expected {expected} query values, got {}",
+                        query.len()
+                    )));
+                }
+
+                // the length check above guarantees there is a pair for every item
+                let mut pairs = query.iter();
+
+                Ok((
+                    $({
+                        let (key, value) = pairs.next().unwrap();
+                        parse_value::<$ty>(key, value)?
+                    },)*
+                    {
+                        let (key, value) = pairs.next().unwrap();
+                        parse_value::<$last>(key, value)?
+                    },
+                ))
+            }
+        }
+    };
+}
+
+all_the_tuples!(impl_from_query);
+
+#[cfg(test)]
+mod tests {
+    use crate::{block_on, trigger, FromQuery, Output};
+
+    use super::*;
+
+    #[derive(FromQuery, Debug, PartialEq)]
+    struct Pagination {
+        page: u32,
+        size: Option<u32>,
+        sort: String,
+    }
+
+    fn query<T: FromQuery>(query: &str) -> Result<T, Rejection> {
+        T::from_query(&QueryPairs::parse(query))
+    }
+
+    #[test]
+    fn parse_decodes_pairs() {
+        let pairs = QueryPairs::parse("name=magic+fn&emoji=%F0%9F%A6%80&empty&bad=%zz&sign=%+1");
+
+        assert_eq!(pairs.get("name"), Some("magic fn"));
+        assert_eq!(pairs.get("emoji"), Some("🦀"));
+        assert_eq!(pairs.get("empty"), Some(""));
+        assert_eq!(pairs.get("bad"), Some("%zz"));
+        assert_eq!(pairs.get("sign"), Some("% 1"));
+        assert_eq!(pairs.get("missing"), None);
+    }
+
+    #[test]
+    fn query_parses_struct() {
+        assert_eq!(
+            query::<Pagination>("page=2&sort=name&size=10"),
+            Ok(Pagination {
+                page: 2,
+                size: Some(10),
+                sort: "name".into()
+            })
+        );
+    }
+
+    #[test]
+    fn query_leaves_missing_optional_field_empty() {
+        let pagination = query::<Pagination>("page=2&sort=name").unwrap();
+        assert_eq!(pagination.size, None);
+    }
+
+    #[test]
+    fn query_rejects_missing_field() {
+        assert_eq!(
+            query::<Pagination>("page=2"),
+            Err(Rejection::new("missing query key `sort`"))
+        );
+    }
+
+    #[test]
+    fn query_rejects_invalid_field() {
+        assert_eq!(
+            query::<Pagination>("page=two&sort=name"),
+            Err(Rejection::new(
+                "invalid query value for `page`: invalid digit found in string"
+            ))
+        );
+    }
+
+    #[test]
+    fn query_parses_tuple_by_position() {
+        assert_eq!(
+            query::<(u32, String, f64)>("a=1&b=magic&c=0.5"),
+            Ok((1, "magic".into(), 0.5))
+        );
+    }
+
+    #[test]
+    fn query_parses_tuple_in_query_order() {
+        assert_eq!(query::<(u32, u32)>("b=2&a=1"), Ok((2, 1)));
+    }
+
+    #[derive(FromQuery, Debug, PartialEq)]
+    struct Pair {
+        a: u32,
+        b: u32,
+    }
+
+    #[test]
+    fn query_parses_struct_in_any_order() {
+        assert_eq!(query::<Pair>("b=2&a=1"), Ok(Pair { a: 1, b: 2 }));
+    }
+
+    #[test]
+    fn query_rejects_tuple_of_other_length() {
+        assert_eq!(
+            query::<(u32, u32)>("a=1"),
+            Err(Rejection::new("expected 2 query values, got 1"))
+        );
+    }
+
+    #[test]
+    fn query_parses_map() {
+        let map = query::<HashMap<String, String>>("a=1&b=2&a=3").unwrap();
+        assert_eq!(map.get("a").map(String::as_str), Some("3"));
+        assert_eq!(map.get("b").map(String::as_str), Some("2"));
+    }
+
+    #[test]
+    fn query_extractor_rejects_context() {
+        let output = block_on(trigger(
+            Context::new("page=1".into(), 33),
+            |Query(pagination): Query<Pagination>| async move { pagination.page },
+        ));
+        assert_eq!(
+            output,
+            Output::Rejected(Rejection::new("missing query key `sort`"))
+        );
+    }
+
+    #[test]
+    fn query_extractor_parses_param() {
+        let output = block_on(trigger(
+            Context::new("page=3&sort=id".into(), 33),
+            |Query((page, sort)): Query<(u32, String)>| async move { format!("{page} {sort}") },
+        ));
+        assert_eq!(output, Output::Text("3 id".into()));
+    }
+}
@@ -0,0 +1,34 @@
+use std::fmt::Display;
+
+use crate::{IntoOutput, Output};
+
+/// Returned by an extractor that could not extract its value from the context.
+/// The handler is not called, its output is the rejection instead
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rejection {
+    message: String,
+}
+
+impl Rejection {
+    pub fn new(message: impl Into<String>) -> Self {
+        Rejection {
+            message: message.into(),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl IntoOutput for Rejection {
+    fn into_output(self) -> Output {
+        Output::Rejected(self)
+    }
+}
@@ -0,0 +1,385 @@
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc};
+
+use crate::{
+    describe::json_string, Context, ExtractorDescription, Handler, IntoOutput, Layer, Output,
+    Rejection, Service,
+};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// A `Service` with its type erased, so handlers of different signatures
+/// can be stored side by side
+pub trait ErasedHandler<S = ()> {
+    fn call(&self, context: Context<S>) -> BoxFuture<'static, Output>;
+
+    fn describe(&self) -> Option<Vec<ExtractorDescription>>;
+}
+
+/// Holds a concrete service behind an `Arc`, so the returned future can own it
+struct BoxedHandler<Svc>(Arc<Svc>);
+
+impl<Svc> ErasedHandler<Svc::State> for BoxedHandler<Svc>
+where
+    Svc: Service + 'static,
+    Svc::State: 'static,
+{
+    fn call(&self, context: Context<Svc::State>) -> BoxFuture<'static, Output> {
+        let service = self.0.clone();
+        Box::pin(async move { service.call(context).await })
+    }
+
+    fn describe(&self) -> Option<Vec<ExtractorDescription>> {
+        self.0.describe()
+    }
+}
+
+/// A type erased route service, this is what router level layers wrap
+pub struct Route<S>(Box<dyn ErasedHandler<S>>);
+
+impl<S> Route<S>
+where
+    S: 'static,
+{
+    pub(crate) fn new<Svc>(service: Svc) -> Self
+    where
+        Svc: Service<State = S> + 'static,
+    {
+        Route(Box::new(BoxedHandler(Arc::new(service))))
+    }
+}
+
+impl<S> Service for Route<S> {
+    type State = S;
+
+    fn call(&self, context: Context<S>) -> impl Future<Output = Output> {
+        self.0.call(context)
+    }
+
+    fn describe(&self) -> Option<Vec<ExtractorDescription>> {
+        self.0.describe()
+    }
+}
+
+/// Dispatches a `Context` to the handler registered for a route
+pub struct Router<S = ()> {
+    routes: HashMap<String, Route<S>>,
+    fallback: Option<Route<S>>,
+    rejection_handler: Option<Box<dyn Fn(Rejection) -> Output>>,
+}
+
+impl<S> Default for Router<S> {
+    fn default() -> Self {
+        Router {
+            routes: HashMap::new(),
+            fallback: None,
+            rejection_handler: None,
+        }
+    }
+}
+
+impl<S> Router<S>
+where
+    S: 'static,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn route<T, H>(self, path: &str, handler: H) -> Self
+    where
+        H: Handler<T, S> + Clone + 'static,
+        T: 'static,
+    {
+        self.route_service(path, handler.into_service())
+    }
+
+    /// Registers a handler that keeps its state between calls, see `Handler::into_service_mut`
+    pub fn route_mut<T, H>(self, path: &str, handler: H) -> Self
+    where
+        H: Handler<T, S> + 'static,
+        T: 'static,
+    {
+        self.route_service(path, handler.into_service_mut())
+    }
+
+    /// Registers a service, e.g. a handler wrapped with layers
+    pub fn route_service<Svc>(mut self, path: &str, service: Svc) -> Self
+    where
+        Svc: Service<State = S> + 'static,
+    {
+        self.routes.insert(path.into(), Route::new(service));
+        self
+    }
+
+    /// Calls `handler` for paths without a route, instead of `dispatch` returning `None`
+    pub fn fallback<T, H>(self, handler: H) -> Self
+    where
+        H: Handler<T, S> + Clone + 'static,
+        T: 'static,
+    {
+        self.fallback_service(handler.into_service())
+    }
+
+    pub fn fallback_service<Svc>(mut self, service: Svc) -> Self
+    where
+        Svc: Service<State = S> + 'static,
+    {
+        self.fallback = Some(Route::new(service));
+        self
+    }
+
+    /// Turns the rejections of every route, including the fallback, into the output of `handler`.
+    /// Without it a rejection is returned as `Output::Rejected`
+    pub fn on_rejection<F, O>(mut self, handler: F) -> Self
+    where
+        F: Fn(Rejection) -> O + 'static,
+        O: IntoOutput,
+    {
+        self.rejection_handler = Some(Box::new(move |rejection| handler(rejection).into_output()));
+        self
+    }
+
+    /// Wraps every route registered so far, and the fallback, with `layer`.
+    /// Routes added after this call are not affected
+    pub fn layer<L>(self, layer: L) -> Self
+    where
+        L: Layer<Route<S>>,
+        L::Service: Service<State = S> + 'static,
+    {
+        let routes = self
+            .routes
+            .into_iter()
+            .map(|(path, route)| (path, Route::new(layer.layer(route))))
+            .collect();
+        let fallback = self.fallback.map(|route| Route::new(layer.layer(route)));
+
+        Router {
+            routes,
+            fallback,
+            rejection_handler: self.rejection_handler,
+        }
+    }
+
+    /// Calls the handler registered for `path`, or the fallback.
+    /// Returns `None` when there is no such route and no fallback
+    pub async fn dispatch(&self, path: &str, context: Context<S>) -> Option<Output> {
+        let handler = self.routes.get(path).or(self.fallback.as_ref())?;
+
+        let output = match (handler.call(context).await, &self.rejection_handler) {
+            (Output::Rejected(rejection), Some(rejection_handler)) => rejection_handler(rejection),
+            (output, _) => output,
+        };
+        Some(output)
+    }
+
+    /// A JSON summary of every route and the extractors of its handler, sorted by path.
+    /// `extractors` is `null` for services that can't describe themselves
+    pub fn describe(&self) -> String {
+        let mut paths: Vec<_> = self.routes.keys().collect();
+        paths.sort();
+
+        let routes: Vec<_> = paths
+            .into_iter()
+            .map(|path| {
+                let extractors = match self.routes[path].describe() {
+                    Some(extractors) if extractors.is_empty() => "[]".to_string(),
+                    Some(extractors) => {
+                        let extractors: Vec<_> = extractors
+                            .iter()
+                            .map(|extractor| format!("        {}", extractor.to_json()))
+                            .collect();
+                        format!("[\n{}\n      ]", extractors.join(",\n"))
+                    }
+                    None => "null".to_string(),
+                };
+
+                format!(
+                    "    {{\n      \"path\": {},\n      \"extractors\": {extractors}\n    }}",
+                    json_string(path)
+                )
+            })
+            .collect();
+
+        if routes.is_empty() {
+            return "{\n  \"routes\": []\n}\n".into();
+        }
+        format!("{{\n  \"routes\": [\n{}\n  ]\n}}\n", routes.join(",\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::task::{Context as TaskContext, Waker};
+
+    use crate::{block_on, executor::tests::YieldOnce, AllowIdsLayer, Id, Param, Path};
+
+    use super::*;
+
+    async fn print_id(Id(id): Id) -> String {
+        format!("id is {id}")
+    }
+
+    async fn print_all(Param(param): Param, Id(id): Id) -> String {
+        format!("param is {param}, id is {id}")
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .route("/id", print_id)
+            .route("/all", print_all)
+            .route("/nothing", || async {})
+    }
+
+    #[test]
+    fn dispatch_calls_handler_for_route() {
+        let router = router();
+        let context = Context::new("magic".into(), 33);
+
+        let id = block_on(router.dispatch("/id", context.clone()));
+        let all = block_on(router.dispatch("/all", context.clone()));
+        let nothing = block_on(router.dispatch("/nothing", context));
+
+        assert_eq!(id, Some(Output::Text("id is 33".into())));
+        assert_eq!(all, Some(Output::Text("param is magic, id is 33".into())));
+        assert_eq!(nothing, Some(Output::Empty));
+    }
+
+    #[test]
+    fn dispatch_calls_handler_on_every_dispatch() {
+        let router = router();
+
+        let first = block_on(router.dispatch("/id", Context::new("magic".into(), 1)));
+        let second = block_on(router.dispatch("/id", Context::new("magic".into(), 2)));
+
+        assert_eq!(first, Some(Output::Text("id is 1".into())));
+        assert_eq!(second, Some(Output::Text("id is 2".into())));
+    }
+
+    fn counter() -> impl FnMut() -> std::future::Ready<u32> + Clone {
+        let mut count = 0;
+        move || {
+            count += 1;
+            std::future::ready(count)
+        }
+    }
+
+    #[test]
+    fn route_mut_keeps_handler_state_between_dispatches() {
+        let router = Router::new().route_mut("/count", counter());
+
+        let outputs: Vec<_> = (0..3)
+            .map(|_| block_on(router.dispatch("/count", Context::new("magic".into(), 33))))
+            .collect();
+
+        assert_eq!(
+            outputs,
+            [
+                Some(Output::Number(1)),
+                Some(Output::Number(2)),
+                Some(Output::Number(3))
+            ]
+        );
+    }
+
+    #[test]
+    fn route_calls_a_fresh_clone_every_dispatch() {
+        let router = Router::new().route("/count", counter());
+
+        let first = block_on(router.dispatch("/count", Context::new("magic".into(), 33)));
+        let second = block_on(router.dispatch("/count", Context::new("magic".into(), 33)));
+
+        assert_eq!(first, Some(Output::Number(1)));
+        assert_eq!(second, Some(Output::Number(1)));
+    }
+
+    #[test]
+    fn route_mut_rejects_calls_while_running() {
+        async fn slow() -> u32 {
+            YieldOnce(false).await
+        }
+
+        let router = Router::new().route_mut("/slow", slow);
+        let mut task = TaskContext::from_waker(Waker::noop());
+
+        let mut first = Box::pin(router.dispatch("/slow", Context::new("magic".into(), 33)));
+        assert!(first.as_mut().poll(&mut task).is_pending());
+
+        let second = block_on(router.dispatch("/slow", Context::new("magic".into(), 33)));
+        assert!(
+            matches!(&second, Some(Output::Error(message)) if message.ends_with("is already running")),
+            "{second:?}"
+        );
+
+        assert_eq!(block_on(first), Some(Output::Number(42)));
+    }
+
+    #[test]
+    fn dispatch_returns_none_for_unknown_route() {
+        let output = block_on(router().dispatch("/unknown", Context::new("magic".into(), 33)));
+        assert_eq!(output, None);
+    }
+
+    #[test]
+    fn dispatch_calls_fallback_for_unknown_route() {
+        let router =
+            router().fallback(|Param(param): Param| async move { format!("no route for {param}") });
+
+        let known = block_on(router.dispatch("/id", Context::new("/id".into(), 33)));
+        let unknown = block_on(router.dispatch("/unknown", Context::new("/unknown".into(), 33)));
+
+        assert_eq!(known, Some(Output::Text("id is 33".into())));
+        assert_eq!(unknown, Some(Output::Text("no route for /unknown".into())));
+    }
+
+    #[test]
+    fn layer_wraps_the_fallback() {
+        let router = router()
+            .fallback(|| async { "fallback".to_string() })
+            .layer(AllowIdsLayer::new([1]));
+
+        let output = block_on(router.dispatch("/unknown", Context::new("magic".into(), 33)));
+
+        assert_eq!(output, Some(Output::Error("id 33 is not allowed".into())));
+    }
+
+    #[test]
+    fn rejection_handler_maps_rejections() {
+        let router = router()
+            .route(
+                "/double",
+                |Path(number): Path<u32>| async move { number * 2 },
+            )
+            .fallback(|Path(number): Path<u32>| async move { number })
+            .on_rejection(|rejection: Rejection| format!("bad request: {rejection}"));
+
+        let valid = block_on(router.dispatch("/double", Context::new("21".into(), 33)));
+        let invalid = block_on(router.dispatch("/double", Context::new("x".into(), 33)));
+        let fallback = block_on(router.dispatch("/unknown", Context::new("y".into(), 33)));
+
+        assert_eq!(valid, Some(Output::Number(42)));
+        assert_eq!(
+            invalid,
+            Some(Output::Text(
+                "bad request: invalid path `x`: invalid digit found in string".into()
+            ))
+        );
+        assert_eq!(
+            fallback,
+            Some(Output::Text(
+                "bad request: invalid path `y`: invalid digit found in string".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn rejections_are_returned_without_rejection_handler() {
+        let router = router().route(
+            "/double",
+            |Path(number): Path<u32>| async move { number * 2 },
+        );
+
+        let output = block_on(router.dispatch("/double", Context::new("x".into(), 33)));
+
+        assert!(matches!(output, Some(Output::Rejected(_))), "{output:?}");
+    }
+}
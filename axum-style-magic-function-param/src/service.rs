@@ -0,0 +1,100 @@
+use std::{any::type_name, cell::RefCell, future::Future, marker::PhantomData};
+
+use crate::{Context, ExtractorDescription, Handler, Layer, Output};
+
+/// Something that turns a `Context` into an `Output`, Towers `Service`.
+/// Unlike `Handler` it is called by reference, so it can wrap other services and be called many times.
+/// Layers operate on services, see `Layer`
+pub trait Service {
+    /// The application state of the contexts this service accepts
+    type State;
+
+    fn call(&self, context: Context<Self::State>) -> impl Future<Output = Output>;
+
+    /// Describes the extractors of the handler behind this service, `None` when it isn't known.
+    /// Layers should forward this to the service they wrap
+    fn describe(&self) -> Option<Vec<ExtractorDescription>> {
+        None
+    }
+}
+
+/// Adds `layer` to every service
+pub trait ServiceExt: Service + Sized {
+    fn layer<L>(self, layer: L) -> L::Service
+    where
+        L: Layer<Self>,
+    {
+        layer.layer(self)
+    }
+}
+
+impl<Svc> ServiceExt for Svc where Svc: Service {}
+
+/// A `Handler` turned into a `Service`, created by `Handler::into_service`
+pub struct HandlerService<H, T, S> {
+    handler: H,
+    _marker: PhantomData<fn() -> (T, S)>,
+}
+
+impl<H, T, S> HandlerService<H, T, S> {
+    pub(crate) fn new(handler: H) -> Self {
+        HandlerService {
+            handler,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<H, T, S> Service for HandlerService<H, T, S>
+where
+    H: Handler<T, S> + Clone,
+{
+    type State = S;
+
+    fn call(&self, context: Context<S>) -> impl Future<Output = Output> {
+        // `Handler::call` consumes the handler, so every call gets a fresh clone
+        self.handler.clone().call(context)
+    }
+
+    fn describe(&self) -> Option<Vec<ExtractorDescription>> {
+        Some(H::describe())
+    }
+}
+
+/// A `Handler` turned into a `Service` that keeps a single instance, created by `Handler::into_service_mut`.
+/// The handler is borrowed mutably for the length of a call, a call made while another one is still
+/// running gets an error output
+pub struct HandlerMutService<H, T, S> {
+    handler: RefCell<H>,
+    _marker: PhantomData<fn() -> (T, S)>,
+}
+
+impl<H, T, S> HandlerMutService<H, T, S> {
+    pub(crate) fn new(handler: H) -> Self {
+        HandlerMutService {
+            handler: RefCell::new(handler),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<H, T, S> Service for HandlerMutService<H, T, S>
+where
+    H: Handler<T, S>,
+{
+    type State = S;
+
+    #[allow(clippy::await_holding_refcell_ref)]
+    async fn call(&self, context: Context<S>) -> Output {
+        let Ok(mut handler) = self.handler.try_borrow_mut() else {
+            return Output::Error(format!("handler `{}` is already running", type_name::<H>()));
+        };
+
+        // holding the borrow across the await is what keeps concurrent calls out
+        handler.call_mut(context).await
+    }
+
+    fn describe(&self) -> Option<Vec<ExtractorDescription>> {
+        Some(H::describe())
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{Context, ExtractorDescription, FromContext, Rejection};
+
+/// Extracts the application state, or a part of it, from the context.
+/// The extracted type must implement `FromRef` for the context state, which every `Clone` state does for itself
+pub struct State<T>(pub T);
+
+/// Creates a substate from a reference to the whole application state,
+/// letting handlers extract just the piece of the state they care about
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` can't be extracted from the state `{T}`",
+    note = "implement `FromRef<{T}>` for `{Self}` to extract it from the state"
+)]
+pub trait FromRef<T> {
+    fn from_ref(input: &T) -> Self;
+}
+
+impl<T> FromRef<T> for T
+where
+    T: Clone,
+{
+    fn from_ref(input: &T) -> Self {
+        input.clone()
+    }
+}
+
+impl<S, T> FromContext<S> for State<T>
+where
+    T: FromRef<S>,
+{
+    fn from_context(context: &Context<S>) -> Result<Self, Rejection> {
+        Ok(State(T::from_ref(context.state())))
+    }
+
+    fn describe() -> ExtractorDescription {
+        ExtractorDescription::of::<Self>("state")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    use crate::{block_on, trigger, Id, Output, Router};
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct AppState {
+        counter: Counter,
+        name: String,
+    }
+
+    #[derive(Clone)]
+    struct Counter(Arc<AtomicU32>);
+
+    impl FromRef<AppState> for Counter {
+        fn from_ref(input: &AppState) -> Self {
+            input.counter.clone()
+        }
+    }
+
+    async fn increment(State(counter): State<Counter>, Id(id): Id) -> u32 {
+        counter.0.fetch_add(id, Ordering::SeqCst) + id
+    }
+
+    async fn describe(State(state): State<AppState>) -> String {
+        let count = state.counter.0.load(Ordering::SeqCst);
+        format!("{} counted {count}", state.name)
+    }
+
+    fn context(id: u32, state: &AppState) -> Context<AppState> {
+        Context::new("magic".into(), id).with_state(state.clone())
+    }
+
+    fn app_state() -> AppState {
+        AppState {
+            counter: Counter(Arc::new(AtomicU32::new(0))),
+            name: "app".into(),
+        }
+    }
+
+    #[test]
+    fn state_extracts_whole_state() {
+        let state = app_state();
+        let output = block_on(trigger(context(1, &state), describe));
+        assert_eq!(output, Output::Text("app counted 0".into()));
+    }
+
+    #[test]
+    fn state_extracts_substate_via_from_ref() {
+        let state = app_state();
+        let output = block_on(trigger(context(5, &state), increment));
+        assert_eq!(output, Output::Number(5));
+    }
+
+    #[derive(crate::FromContext)]
+    #[from_context(state = AppState)]
+    struct CountRequest {
+        counter: State<Counter>,
+        id: Id,
+    }
+
+    #[test]
+    fn derived_extractor_extracts_substate() {
+        let state = app_state();
+        let output = block_on(trigger(
+            context(4, &state),
+            |CountRequest { counter, id }: CountRequest| async move {
+                counter.0 .0.fetch_add(id.0, Ordering::SeqCst) + id.0
+            },
+        ));
+        assert_eq!(output, Output::Number(4));
+    }
+
+    #[test]
+    fn handlers_share_state_through_router() {
+        let state = app_state();
+        let router = Router::new()
+            .route("/increment", increment)
+            .route("/describe", describe);
+
+        block_on(router.dispatch("/increment", context(2, &state)));
+        block_on(router.dispatch("/increment", context(3, &state)));
+        let output = block_on(router.dispatch("/describe", context(0, &state)));
+
+        assert_eq!(output, Some(Output::Text("app counted 5".into())));
+    }
+}
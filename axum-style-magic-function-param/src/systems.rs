@@ -0,0 +1,323 @@
+use std::{
+    any::{type_name, Any, TypeId},
+    cell::{Ref, RefCell, RefMut},
+    collections::HashMap,
+    fmt::Display,
+    ops::{Deref, DerefMut},
+};
+
+/// A type map of singletons, the `Context` of systems.
+/// Each resource is borrow checked at runtime, so systems can't hold a `ResMut` alongside another borrow of the same resource
+#[derive(Default)]
+pub struct Resources {
+    map: HashMap<TypeId, RefCell<Box<dyn Any>>>,
+}
+
+impl Resources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert<T>(&mut self, value: T)
+    where
+        T: 'static,
+    {
+        self.map
+            .insert(TypeId::of::<T>(), RefCell::new(Box::new(value)));
+    }
+
+    pub fn get<T>(&self) -> Result<Res<'_, T>, SystemError>
+    where
+        T: 'static,
+    {
+        let borrowed = self
+            .cell::<T>()?
+            .try_borrow()
+            .map_err(|_| SystemError::BorrowConflict(type_name::<T>()))?;
+
+        // the map is keyed by `TypeId`, so the downcast can't fail
+        Ok(Res(Ref::map(borrowed, |value| {
+            value.downcast_ref().unwrap()
+        })))
+    }
+
+    pub fn get_mut<T>(&self) -> Result<ResMut<'_, T>, SystemError>
+    where
+        T: 'static,
+    {
+        let borrowed = self
+            .cell::<T>()?
+            .try_borrow_mut()
+            .map_err(|_| SystemError::BorrowConflict(type_name::<T>()))?;
+
+        Ok(ResMut(RefMut::map(borrowed, |value| {
+            value.downcast_mut().unwrap()
+        })))
+    }
+
+    fn cell<T>(&self) -> Result<&RefCell<Box<dyn Any>>, SystemError>
+    where
+        T: 'static,
+    {
+        self.map
+            .get(&TypeId::of::<T>())
+            .ok_or(SystemError::MissingResource(type_name::<T>()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SystemError {
+    /// No resource of this type was inserted
+    MissingResource(&'static str),
+    /// The resource is already borrowed in a way that conflicts with this borrow
+    BorrowConflict(&'static str),
+}
+
+impl Display for SystemError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SystemError::MissingResource(name) => write!(f, "missing resource `{name}`"),
+            SystemError::BorrowConflict(name) => write!(f, "resource `{name}` is already borrowed"),
+        }
+    }
+}
+
+/// Shared access to the resource `T`
+pub struct Res<'w, T>(Ref<'w, T>);
+
+impl<T> Deref for Res<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// Exclusive access to the resource `T`
+pub struct ResMut<'w, T>(RefMut<'w, T>);
+
+impl<T> Deref for ResMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for ResMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+/// The `FromContext` of systems - fetches a system argument from the resources.
+/// Arguments borrow the resources, `Item<'w>` is the argument type for resources borrowed for `'w`
+pub trait SystemParam {
+    type Item<'w>;
+
+    fn fetch(resources: &Resources) -> Result<Self::Item<'_>, SystemError>;
+}
+
+impl<T> SystemParam for Res<'_, T>
+where
+    T: 'static,
+{
+    type Item<'w> = Res<'w, T>;
+
+    fn fetch(resources: &Resources) -> Result<Self::Item<'_>, SystemError> {
+        resources.get()
+    }
+}
+
+impl<T> SystemParam for ResMut<'_, T>
+where
+    T: 'static,
+{
+    type Item<'w> = ResMut<'w, T>;
+
+    fn fetch(resources: &Resources) -> Result<Self::Item<'_>, SystemError> {
+        resources.get_mut()
+    }
+}
+
+/// The `Handler` of systems - a function whose arguments are all `SystemParam`s
+pub trait System<Params> {
+    fn run(&mut self, resources: &Resources) -> Result<(), SystemError>;
+}
+
+/// Implements `System` for functions of every arity.
+///
+/// A function like `fn(Res<Counter>)` takes a `Res` of any lifetime, so it implements `FnMut(P1)`
+/// for some `P1` which lets the compiler infer the params, and `FnMut(P1::Item<'w>)` for every `'w`,
+/// which lets us call it with resources borrowed just for this run
+macro_rules! impl_system {
+    ([]) => {
+        impl_system!(@impl);
+    };
+    ([$($ty:ident),*], $last:ident) => {
+        impl_system!(@impl $($ty,)* $last);
+    };
+    (@impl $($ty:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<F, $($ty,)*> System<($($ty,)*)> for F
+        where
+            F: FnMut($($ty),*) + for<'w> FnMut($($ty::Item<'w>),*),
+            $($ty: SystemParam,)*
+        {
+            #[allow(unused_variables)]
+            fn run(&mut self, resources: &Resources) -> Result<(), SystemError> {
+                // pins down which `FnMut` implementation to call, the compiler can't choose on its own
+                #[allow(clippy::too_many_arguments)]
+                fn call<$($ty,)*>(mut f: impl FnMut($($ty),*), $($ty: $ty,)*) {
+                    f($($ty),*)
+                }
+
+                $(let $ty = $ty::fetch(resources)?;)*
+                call(self, $($ty,)*);
+                Ok(())
+            }
+        }
+    };
+}
+
+all_the_tuples!(impl_system);
+
+type BoxedSystem = Box<dyn FnMut(&Resources) -> Result<(), SystemError>>;
+
+/// Runs systems one after the other, in the order they were added
+#[derive(Default)]
+pub struct Schedule {
+    systems: Vec<BoxedSystem>,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_system<Params, Sys>(mut self, mut system: Sys) -> Self
+    where
+        Sys: System<Params> + 'static,
+    {
+        self.systems
+            .push(Box::new(move |resources| system.run(resources)));
+        self
+    }
+
+    /// Runs every system, stopping at the first one that fails to fetch its params
+    pub fn run(&mut self, resources: &Resources) -> Result<(), SystemError> {
+        self.systems
+            .iter_mut()
+            .try_for_each(|system| system(resources))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter {
+        count: u32,
+    }
+
+    struct Log {
+        lines: Vec<String>,
+    }
+
+    fn increment(mut counter: ResMut<Counter>) {
+        counter.count += 1;
+    }
+
+    fn log_count(counter: Res<Counter>, mut log: ResMut<Log>) {
+        log.lines.push(format!("count is {}", counter.count));
+    }
+
+    fn resources() -> Resources {
+        let mut resources = Resources::new();
+        resources.insert(Counter { count: 0 });
+        resources.insert(Log { lines: Vec::new() });
+        resources
+    }
+
+    #[test]
+    fn schedule_runs_systems_in_order() {
+        let resources = resources();
+        let mut schedule = Schedule::new()
+            .add_system(increment)
+            .add_system(log_count)
+            .add_system(increment)
+            .add_system(increment)
+            .add_system(log_count);
+
+        schedule.run(&resources).unwrap();
+
+        assert_eq!(
+            resources.get::<Log>().unwrap().lines,
+            ["count is 1", "count is 3"]
+        );
+    }
+
+    #[test]
+    fn schedule_can_run_many_times() {
+        let resources = resources();
+        let mut schedule = Schedule::new().add_system(increment);
+
+        schedule.run(&resources).unwrap();
+        schedule.run(&resources).unwrap();
+
+        assert_eq!(resources.get::<Counter>().unwrap().count, 2);
+    }
+
+    #[test]
+    fn system_can_share_a_resource() {
+        let resources = resources();
+        let mut system = |a: Res<Counter>, b: Res<Counter>| assert_eq!(a.count, b.count);
+
+        assert_eq!(system.run(&resources), Ok(()));
+    }
+
+    #[test]
+    fn system_rejects_conflicting_mutable_borrow() {
+        let resources = resources();
+        let mut system = |_: ResMut<Counter>, _: Res<Counter>| {};
+
+        assert_eq!(
+            system.run(&resources),
+            Err(SystemError::BorrowConflict(type_name::<Counter>()))
+        );
+    }
+
+    #[test]
+    fn system_rejects_two_mutable_borrows() {
+        let resources = resources();
+        let mut system = |_: ResMut<Counter>, _: ResMut<Counter>| {};
+
+        assert_eq!(
+            system.run(&resources),
+            Err(SystemError::BorrowConflict(type_name::<Counter>()))
+        );
+    }
+
+    #[test]
+    fn system_rejects_missing_resource() {
+        let resources = Resources::new();
+        let mut schedule = Schedule::new().add_system(increment);
+
+        assert_eq!(
+            schedule.run(&resources),
+            Err(SystemError::MissingResource(type_name::<Counter>()))
+        );
+    }
+
+    #[test]
+    fn system_can_capture_state() {
+        let resources = resources();
+        let mut runs = 0;
+        let mut system = |_: Res<Counter>| runs += 1;
+
+        system.run(&resources).unwrap();
+        system.run(&resources).unwrap();
+
+        assert_eq!(runs, 2);
+    }
+}
@@ -0,0 +1,79 @@
+use std::{env, fs};
+
+use rust_magic_fn::{
+    AllowIdsLayer, Body, Context, Event, Extension, FromContext, FromQuery, Handler, Id, Output,
+    Param, Path, Query, Router, Service, State, TimingLayer,
+};
+
+#[derive(FromQuery)]
+struct Pagination {
+    #[allow(dead_code)]
+    page: u32,
+}
+
+#[derive(FromContext)]
+#[allow(dead_code)]
+struct Caller {
+    id: Id,
+    param: Param,
+}
+
+#[derive(Clone)]
+struct User;
+
+async fn index() {}
+
+async fn show(Path(_): Path<u32>, State(_): State<()>) {}
+
+async fn list(Query(_): Query<Pagination>, Extension(_): Extension<User>) {}
+
+async fn upload(Id(_): Id, Caller { .. }: Caller, Body(_): Body) {}
+
+async fn on_event(Event(_): Event<u32>) {}
+
+/// A service written by hand, it can't tell what it extracts
+struct Static;
+
+impl Service for Static {
+    type State = ();
+
+    async fn call(&self, _context: Context) -> Output {
+        Output::Empty
+    }
+}
+
+/// Compares against the snapshot in `tests/snapshots`, run with `SNAPSHOTS=overwrite` to update it
+fn assert_snapshot(name: &str, actual: &str) {
+    let path = format!("{}/tests/snapshots/{name}", env!("CARGO_MANIFEST_DIR"));
+
+    if env::var("SNAPSHOTS").as_deref() == Ok("overwrite") {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).unwrap_or_default();
+    assert_eq!(
+        actual, expected,
+        "{name} doesn't match its snapshot, run with SNAPSHOTS=overwrite to update it"
+    );
+}
+
+#[test]
+fn router_description_snapshot() {
+    let router = Router::new()
+        .route("/", index)
+        .route("/show", show)
+        .route("/list", list)
+        .route("/upload", upload)
+        .route("/event", on_event)
+        .route_service("/secret", show.layer(AllowIdsLayer::new([1])))
+        .route_service("/static", Static)
+        .layer(TimingLayer::new(|_| {}));
+
+    assert_snapshot("routes.json", &router.describe());
+}
+
+#[test]
+fn empty_router_description_snapshot() {
+    assert_snapshot("empty.json", &Router::<()>::new().describe());
+}
@@ -0,0 +1,155 @@
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use rust_magic_fn::{serve, serve_with, Body, Id, Param, Router};
+
+async fn whoami(Param(path): Param, Id(id): Id) -> String {
+    format!("{path} {id}")
+}
+
+async fn echo(Body(body): Body) -> String {
+    String::from_utf8_lossy(&body).into_owned()
+}
+
+async fn nothing() {}
+
+async fn divide(Id(id): Id) -> Result<u32, String> {
+    100u32.checked_div(id).ok_or("can't divide by id 0".into())
+}
+
+/// Starts a server on an ephemeral port, the router is built on the server thread since it isn't `Send`
+fn start() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        let router = Router::new()
+            .route("/whoami", whoami)
+            .route("/echo", echo)
+            .route("/nothing", nothing)
+            .route("/divide", divide);
+        serve(listener, &router)
+    });
+
+    addr
+}
+
+fn send(addr: SocketAddr, request: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+fn status_and_body(response: &str) -> (&str, &str) {
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head.lines().next().unwrap(), body)
+}
+
+#[test]
+fn path_and_id_header_are_extracted() {
+    let addr = start();
+
+    let response = send(
+        addr,
+        "GET /whoami HTTP/1.1\r\nHost: localhost\r\nX-Id: 33\r\n\r\n",
+    );
+
+    assert_eq!(
+        status_and_body(&response),
+        ("HTTP/1.1 200 OK", "/whoami 33")
+    );
+}
+
+#[test]
+fn body_is_extracted() {
+    let addr = start();
+
+    let response = send(
+        addr,
+        "POST /echo HTTP/1.1\r\nContent-Length: 12\r\n\r\nsome payload",
+    );
+
+    assert_eq!(
+        status_and_body(&response),
+        ("HTTP/1.1 200 OK", "some payload")
+    );
+}
+
+#[test]
+fn outputs_map_to_status_codes() {
+    let addr = start();
+
+    let cases = [
+        ("/nothing", "", ("HTTP/1.1 204 No Content", "")),
+        ("/divide", "X-Id: 4\r\n", ("HTTP/1.1 200 OK", "25")),
+        (
+            "/divide",
+            "",
+            ("HTTP/1.1 500 Internal Server Error", "can't divide by id 0"),
+        ),
+        (
+            "/unknown",
+            "",
+            ("HTTP/1.1 404 Not Found", "no route for /unknown"),
+        ),
+    ];
+
+    for (path, headers, expected) in cases {
+        let response = send(addr, &format!("GET {path} HTTP/1.1\r\n{headers}\r\n"));
+        assert_eq!(status_and_body(&response), expected, "{path}");
+    }
+}
+
+#[test]
+fn malformed_request_is_bad_request() {
+    let addr = start();
+
+    let response = send(addr, "GET /whoami HTTP/1.1\r\nX-Id: thirty three\r\n\r\n");
+    let (status, body) = status_and_body(&response);
+
+    assert_eq!(status, "HTTP/1.1 400 Bad Request");
+    assert!(body.starts_with("invalid id `thirty three`"), "{body}");
+}
+
+#[test]
+fn server_keeps_serving_after_a_request() {
+    let addr = start();
+
+    for id in 1..=3 {
+        let response = send(addr, &format!("GET /whoami HTTP/1.1\r\nX-Id: {id}\r\n\r\n"));
+        assert_eq!(status_and_body(&response).1, format!("/whoami {id}"));
+    }
+}
+
+#[test]
+fn silent_client_times_out_without_blocking_others() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (errors, failed) = mpsc::channel();
+
+    thread::spawn(move || {
+        let router = Router::new().route("/whoami", whoami);
+        serve_with(listener, &router, Duration::from_millis(100), |err| {
+            errors.send(err.kind()).unwrap()
+        })
+    });
+
+    // connects but never sends a request
+    let _silent = TcpStream::connect(addr).unwrap();
+    let response = send(addr, "GET /whoami HTTP/1.1\r\nX-Id: 7\r\n\r\n");
+
+    assert_eq!(status_and_body(&response).1, "/whoami 7");
+    let kind = failed.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(
+        matches!(kind, io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut),
+        "{kind:?}"
+    );
+}
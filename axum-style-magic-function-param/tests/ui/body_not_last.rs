@@ -0,0 +1,9 @@
+use rust_magic_fn::{block_on, trigger, Body, Context, Id};
+
+async fn handler(Body(body): Body, Id(id): Id) -> String {
+    format!("{id} {}", body.len())
+}
+
+fn main() {
+    block_on(trigger(Context::new("magic".into(), 33), handler));
+}
@@ -0,0 +1,9 @@
+use rust_magic_fn::{block_on, trigger, Body, Context};
+
+async fn handler(Body(first): Body, Body(second): Body) -> usize {
+    first.len() + second.len()
+}
+
+fn main() {
+    block_on(trigger(Context::new("magic".into(), 33), handler));
+}
@@ -0,0 +1,8 @@
+use rust_magic_fn::{debug_handler, Id};
+
+#[debug_handler]
+async fn handler(Id(id): Id) -> Vec<u32> {
+    vec![id]
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use rust_magic_fn::{debug_handler, Body, Id};
+
+#[debug_handler]
+async fn handler(Body(body): Body, Id(id): Id) -> String {
+    format!("{id} {}", body.len())
+}
+
+fn main() {}
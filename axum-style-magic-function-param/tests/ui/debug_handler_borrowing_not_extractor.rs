@@ -0,0 +1,8 @@
+use rust_magic_fn::{debug_handler, Id};
+
+#[debug_handler]
+async fn handler(name: &str, Id(id): Id) -> String {
+    format!("{name} {id}")
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use rust_magic_fn::{debug_handler, FromContext};
+
+#[debug_handler]
+async fn handler<T: FromContext>(_: T) {}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use rust_magic_fn::{debug_handler, Id};
+
+#[debug_handler]
+fn handler(Id(id): Id) -> u32 {
+    id
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use rust_magic_fn::{debug_handler, Id};
+
+#[debug_handler]
+async fn handler(Id(id): Id, name: String) -> String {
+    format!("{id} {name}")
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use rust_magic_fn::{debug_handler, State};
+
+#[derive(Clone)]
+struct AppState;
+
+#[derive(Clone)]
+struct OtherState;
+
+#[debug_handler(state = AppState)]
+async fn handler(State(_): State<OtherState>) {}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use rust_magic_fn::{FromContext, Id};
+
+#[derive(FromContext)]
+struct Request {
+    id: Id,
+    name: String,
+}
+
+fn main() {}
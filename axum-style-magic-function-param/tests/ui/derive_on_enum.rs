@@ -0,0 +1,9 @@
+use rust_magic_fn::FromContext;
+
+#[derive(FromContext)]
+enum Request {
+    A,
+    B,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use rust_magic_fn::{FromContext, Id};
+
+#[derive(FromContext)]
+#[from_context(status = u32)]
+struct Request {
+    id: Id,
+}
+
+fn main() {}